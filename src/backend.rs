@@ -0,0 +1,93 @@
+use crate::geom::*;
+use crate::keys::{Keycode, Modifiers};
+use crate::session::{HistoryEntry, KeyPress, Monitor, SizeHints, Strut, WindowState, WindowType};
+
+use anyhow::Result;
+use std::os::fd::BorrowedFd;
+
+// Backend is the display server, as far as mosaic is concerned. Session keeps track of the window
+// tree and the monitors, and the backend is what it asks to find them out, and to change things.
+// Windows are always referred to by id.
+//
+// The X server (through XCB) is the real one. There's also an in-memory fake for the tests.
+pub(crate) trait Backend {
+    fn root(&self) -> u32;
+
+    // load_windows finds every window in the tree, starting from the root
+    fn load_windows(&self) -> Vec<WindowInfo>;
+    // load_window finds out about a single window again. None if its gone away. children aren't
+    // filled in, because the session already knows them
+    fn load_window(&self, id: u32, parent: u32) -> Option<WindowInfo>;
+
+    // monitors are the physical outputs, in whatever order the backend finds them. empty if it
+    // can't tell, and the session will work something out
+    fn monitors(&self) -> Result<Vec<Monitor>>;
+
+    fn current_desktop(&self) -> Option<u32>;
+    fn desktop_count(&self) -> Option<u32>;
+    // workarea is the area the WM says windows can use on the current desktop, if it says
+    fn workarea(&self) -> Option<Box2D>;
+
+    // active_window is 0 if there isn't one
+    fn active_window(&self) -> Result<u32>;
+    // select_window lets the user pick a window with the pointer
+    fn select_window(&self) -> Result<u32>;
+    fn pointer(&self) -> Result<Point2D>;
+
+    fn frame_extents(&self, id: u32) -> Result<SideOffsets2D>;
+    fn state(&self, id: u32) -> Result<WindowState>;
+    fn history(&self, id: u32) -> Result<Vec<HistoryEntry>>;
+    fn name(&self, id: u32) -> Result<String>;
+    // class is (instance, class)
+    fn class(&self, id: u32) -> Result<Option<(String, String)>>;
+    fn role(&self, id: u32) -> Result<Option<String>>;
+    fn pid(&self, id: u32) -> Result<Option<u32>>;
+    fn is_viewable(&self, id: u32) -> Result<bool>;
+    fn size_hints(&self, id: u32) -> Result<SizeHints>;
+
+    // set_geom asks for the window itself (not including the frame) to be moved and resized
+    fn set_geom(&self, id: u32, geom: &Box2D) -> Result<()>;
+    // set_state turns the given states on or off. the rest are left alone
+    fn set_state(&self, id: u32, state: WindowState, on: bool) -> Result<()>;
+    fn set_desktop(&self, id: u32, desktop: u32) -> Result<()>;
+    fn set_history(&self, id: u32, history: &[HistoryEntry]) -> Result<()>;
+
+    // watch starts collecting events, for a long-running session. poll_events returns the ones
+    // that have arrived since last time, without blocking. event_fd becomes readable when there
+    // might be more. None if there's nothing to wait on, because events only come from what we do
+    fn watch(&self) -> Result<()>;
+    fn poll_events(&self) -> Result<Vec<Event>>;
+    fn event_fd(&self) -> Option<BorrowedFd<'_>>;
+
+    // grab_key asks for presses of a key with the modifiers held to be reported as events, no
+    // matter which window has focus. returns the keycodes that were grabbed
+    fn grab_key(&self, mods: Modifiers, keysym: u32) -> Result<Vec<Keycode>>;
+}
+
+// WindowInfo is what the backend knows about a window up front, without asking for more
+#[derive(Clone, Debug)]
+pub(crate) struct WindowInfo {
+    pub id: u32,
+    pub parent: u32,
+    pub children: Vec<u32>,
+    // relative to the parent
+    pub geom: Box2D,
+    pub typ: WindowType,
+    pub selectable: bool,
+    pub strut: Option<Strut>,
+    pub desktop: Option<u32>,
+}
+
+// Event is something that changed since the session last looked
+#[derive(Clone, Debug)]
+pub(crate) enum Event {
+    Created { id: u32, parent: u32 },
+    Destroyed(u32),
+    // the window moved or resized. geom is relative to the parent
+    Configured { id: u32, geom: Box2D },
+    Reparented { id: u32, parent: u32, pos: Point2D },
+    // something we keep about the window changed, so it needs to be loaded again
+    Changed(u32),
+    MonitorsChanged,
+    KeyPress(KeyPress),
+}
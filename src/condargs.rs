@@ -11,7 +11,7 @@ where
     C: Comparator,
 {
     pub fn matches(&self, v: C::Value) -> bool {
-        self.cond.as_ref().map_or(true, |c| c.matches(v))
+        self.cond.as_ref().is_none_or(|c| c.matches(v))
     }
 }
 
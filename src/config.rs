@@ -0,0 +1,317 @@
+use crate::engine::{
+    HintsPolicyArgs, HorizAlignArgs, MonitorArg, MonitorPolicyArgs, VertAlignArgs,
+};
+use crate::grid::{GridCell, GridSize};
+use crate::length::{LengthArg, MarginArg, NudgeArg};
+use crate::size::{Size, SizeForRatioArg};
+use crate::DesktopArg;
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use log::debug;
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+// Config is the user's config file. Everything in it is optional, and a missing file is the same
+// as an empty one.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Config {
+    // key chord (eg "Ctrl+Super+Left") to the mosaic command line to run when its pressed
+    pub keys: BTreeMap<String, CommandLine>,
+
+    // named sets of placement options, for --preset
+    pub presets: BTreeMap<String, Preset>,
+}
+
+// Preset is a set of placement options, written the same way as on the command line (eg
+// width = ["50@<1.5", "33"]). anything not given is left to the command line.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Preset {
+    #[serde(deserialize_with = "value_enum")]
+    pub halign: Option<HorizAlignArgs>,
+    #[serde(deserialize_with = "value_enum")]
+    pub valign: Option<VertAlignArgs>,
+    #[serde(deserialize_with = "from_str")]
+    pub x: Option<Size>,
+    #[serde(deserialize_with = "from_str")]
+    pub y: Option<Size>,
+    #[serde(deserialize_with = "from_str")]
+    pub nudge: Option<NudgeArg>,
+    #[serde(deserialize_with = "from_str_list")]
+    pub width: Option<Vec<SizeForRatioArg>>,
+    #[serde(deserialize_with = "from_str_list")]
+    pub height: Option<Vec<SizeForRatioArg>>,
+    #[serde(deserialize_with = "from_str")]
+    pub monitor: Option<MonitorArg>,
+    #[serde(deserialize_with = "from_str")]
+    pub gap: Option<LengthArg>,
+    #[serde(deserialize_with = "from_str")]
+    pub margin: Option<MarginArg>,
+    #[serde(deserialize_with = "from_str")]
+    pub grid: Option<GridSize>,
+    #[serde(deserialize_with = "from_str")]
+    pub cell: Option<GridCell>,
+    #[serde(deserialize_with = "from_str")]
+    pub span: Option<GridSize>,
+    pub maximize: bool,
+    #[serde(deserialize_with = "value_enum")]
+    pub monitor_policy: Option<MonitorPolicyArgs>,
+    #[serde(deserialize_with = "value_enum")]
+    pub hints_policy: Option<HintsPolicyArgs>,
+    #[serde(deserialize_with = "from_str")]
+    pub desktop: Option<DesktopArg>,
+}
+
+// Scalar is a single option value. numbers are allowed so that eg width = 50 works
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Scalar {
+    String(String),
+    Int(i64),
+    Float(f64),
+}
+
+impl Scalar {
+    fn parse<T>(self) -> Result<T, String>
+    where
+        T: FromStr,
+        T::Err: Debug,
+    {
+        let s = match self {
+            Scalar::String(s) => s,
+            Scalar::Int(n) => n.to_string(),
+            Scalar::Float(n) => n.to_string(),
+        };
+        s.parse::<T>()
+            .map_err(|e| format!("invalid value {:?}: {:?}", s, e))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(Scalar),
+    Many(Vec<Scalar>),
+}
+
+fn from_str<'de, D, T>(d: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Debug,
+{
+    Scalar::deserialize(d)?
+        .parse()
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+fn from_str_list<'de, D, T>(d: D) -> Result<Option<Vec<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Debug,
+{
+    let values = match OneOrMany::deserialize(d)? {
+        OneOrMany::One(v) => vec![v],
+        OneOrMany::Many(vs) => vs,
+    };
+    values
+        .into_iter()
+        .map(Scalar::parse)
+        .collect::<Result<Vec<_>, _>>()
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+fn value_enum<'de, D, T>(d: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: ValueEnum,
+{
+    let s = String::deserialize(d)?;
+    T::from_str(&s, true)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+// CommandLine is a set of mosaic arguments. As a string, its split on whitespace (no quoting);
+// if an argument needs spaces, use a list instead.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub(crate) enum CommandLine {
+    Line(String),
+    Args(Vec<String>),
+}
+
+impl CommandLine {
+    pub(crate) fn args(&self) -> Vec<String> {
+        match self {
+            CommandLine::Line(line) => line.split_whitespace().map(String::from).collect(),
+            CommandLine::Args(args) => args.clone(),
+        }
+    }
+}
+
+// default_path is $XDG_CONFIG_HOME/mosaic/config.toml, or ~/.config/mosaic/config.toml
+fn default_path() -> Option<PathBuf> {
+    let dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(dir.join("mosaic").join("config.toml"))
+}
+
+impl Config {
+    // load reads the config from the given path, or the default path if there isn't one. Its
+    // only an error for the default file to be missing if it was asked for explicitly.
+    pub(crate) fn load(path: Option<&Path>) -> Result<Config> {
+        let (path, required) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Config::default()),
+            },
+        };
+
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if !required && e.kind() == std::io::ErrorKind::NotFound => {
+                debug!("no config file at {}", path.display());
+                return Ok(Config::default());
+            }
+            Err(e) => {
+                return Err(e).with_context(|| format!("couldn't read {}", path.display()));
+            }
+        };
+
+        debug!("loading config from {}", path.display());
+        toml::from_str(&text).with_context(|| format!("couldn't parse {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::size::SizeArg;
+
+    fn parse(text: &str) -> Result<Config, toml::de::Error> {
+        toml::from_str(text)
+    }
+
+    #[test]
+    fn presets() {
+        let config = parse(
+            r#"
+            [presets.left]
+            halign = "LEFT"
+            width = ["50@<1.5", "33"]
+            height = 100
+            margin = "10,5%"
+            nudge = "0,-20"
+
+            [presets.grid]
+            grid = "3x2"
+            cell = "1,0"
+            span = "2x1"
+            monitor = "next"
+            maximize = true
+            monitor_policy = "Pointer"
+            hints_policy = "expand"
+            desktop = 2
+            "#,
+        )
+        .unwrap();
+
+        let left = &config.presets["left"];
+        assert!(matches!(left.halign, Some(HorizAlignArgs::Left)));
+        assert!(left.valign.is_none());
+
+        let width = left.width.as_ref().unwrap();
+        assert_eq!(width.len(), 2);
+        assert!(width[0].matches(1.2) && !width[0].matches(1.8));
+        assert!(matches!(width[0].value, SizeArg::Fixed(s) if s == "50".parse().unwrap()));
+        assert!(width[1].matches(1.8));
+
+        // a bare number is the same as a string
+        let height = left.height.as_ref().unwrap();
+        assert!(matches!(height[0].value, SizeArg::Fixed(s) if s == "100".parse().unwrap()));
+
+        assert!(matches!(
+            left.margin,
+            Some(MarginArg {
+                top: LengthArg::Pixels(10),
+                right: LengthArg::Percent(5),
+                bottom: LengthArg::Pixels(10),
+                left: LengthArg::Percent(5),
+            })
+        ));
+        assert!(matches!(
+            left.nudge,
+            Some(NudgeArg {
+                dx: LengthArg::Pixels(0),
+                dy: LengthArg::Pixels(-20),
+            })
+        ));
+
+        let grid = &config.presets["grid"];
+        assert_eq!(grid.grid, Some(GridSize { cols: 3, rows: 2 }));
+        assert_eq!(grid.cell, Some(GridCell { col: 1, row: 0 }));
+        assert_eq!(grid.span, Some(GridSize { cols: 2, rows: 1 }));
+        assert!(matches!(grid.monitor, Some(MonitorArg::Next)));
+        assert!(grid.width.is_none());
+        assert!(grid.maximize);
+        assert_eq!(grid.monitor_policy, Some(MonitorPolicyArgs::Pointer));
+        assert_eq!(grid.hints_policy, Some(HintsPolicyArgs::Expand));
+        assert!(matches!(grid.desktop, Some(DesktopArg::Index(2))));
+
+        assert!(!left.maximize);
+        assert!(left.monitor_policy.is_none() && left.hints_policy.is_none());
+        assert!(left.desktop.is_none());
+    }
+
+    #[test]
+    fn keys() {
+        let config = parse(
+            r#"
+            [keys]
+            "Ctrl+Super+Left" = "--active  --preset left"
+            "Ctrl+Super+T" = ["--title", "a b", "--halign", "left"]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.keys["Ctrl+Super+Left"].args(),
+            ["--active", "--preset", "left"]
+        );
+        assert_eq!(
+            config.keys["Ctrl+Super+T"].args(),
+            ["--title", "a b", "--halign", "left"]
+        );
+    }
+
+    #[test]
+    fn bad_presets() {
+        for text in [
+            // not a real option
+            "[presets.p]\nwdth = 50",
+            // center isn't one of the alignments
+            "[presets.p]\nhalign = \"center\"",
+            "[presets.p]\nwidth = \"wide\"",
+            "[presets.p]\ngrid = \"0x2\"",
+            "[presets.p]\nmargin = \"1,2,3,4,5\"",
+            "[presets.p]\nmaximize = \"yes\"",
+            "[presets.p]\nhints_policy = \"stretch\"",
+            "[presets.p]\ndesktop = \"last\"",
+            "[other]\nx = 1",
+        ] {
+            assert!(parse(text).is_err(), "{}", text);
+        }
+    }
+}
@@ -0,0 +1,399 @@
+use crate::config::Config;
+use crate::keys::{KeyChord, Keycode, Modifiers};
+use crate::session::Session;
+use crate::{apply_preset, check_args, run, RootArgs};
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use clap::Parser;
+use log::{debug, info, warn};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs::Permissions;
+use std::io::{ErrorKind, Read, Write};
+use std::net::Shutdown;
+use std::os::fd::AsRawFd;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Duration;
+
+// how long we'll wait for a client to send its command, so a broken one can't wedge the daemon
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(1);
+
+// The protocol is as simple as it gets. The client sends its command line arguments, each
+// terminated by a NUL, and closes its side. The daemon runs the command, and replies with a line
+// saying "ok" or "error", followed by the error message if there was one.
+
+// socket_path is where the daemon listens. A daemon is tied to one X server, so there's one per
+// display. XDG_RUNTIME_DIR is private to the user, but without it the socket goes in a directory
+// of our own in /tmp, because anyone can make files there.
+fn socket_path() -> PathBuf {
+    let display = std::env::var("DISPLAY").unwrap_or_default();
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir).join(format!("mosaic-{}.sock", display)),
+        _ => std::env::temp_dir()
+            .join(format!("mosaic-{}", uid()))
+            .join(format!("{}.sock", display)),
+    }
+}
+
+// make_socket_dir makes sure the directory the socket goes in exists, and that no one else can
+// get into it. someone else could have made it first, so if its already there we check it
+fn make_socket_dir(path: &Path) -> Result<()> {
+    let Some(dir) = path.parent() else {
+        return Ok(());
+    };
+    match std::fs::DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => return Ok(()),
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(e).with_context(|| format!("couldn't create {}", dir.display())),
+    }
+    let meta = std::fs::symlink_metadata(dir)
+        .with_context(|| format!("couldn't look at {}", dir.display()))?;
+    ensure!(
+        meta.is_dir() && meta.uid() == uid() && meta.mode() & 0o077 == 0,
+        "{} isn't a private directory",
+        dir.display()
+    );
+    Ok(())
+}
+
+fn uid() -> u32 {
+    // SAFETY: getuid() has no failure modes
+    unsafe { libc::getuid() }
+}
+
+// peer_uid is the user on the other end of a socket, according to the kernel
+#[cfg(target_os = "linux")]
+fn peer_uid(stream: &UnixStream) -> std::io::Result<u32> {
+    let mut cred = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // SAFETY: cred and len are valid for the duration of the call, and len is the size of cred
+    let ret = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            (&mut cred as *mut libc::ucred).cast(),
+            &mut len,
+        )
+    };
+    if ret < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(cred.uid)
+}
+
+// the BSDs and macOS don't have SO_PEERCRED, but they all have getpeereid
+#[cfg(not(target_os = "linux"))]
+fn peer_uid(stream: &UnixStream) -> std::io::Result<u32> {
+    let mut uid = 0;
+    let mut gid = 0;
+    // SAFETY: uid and gid are valid for the duration of the call
+    if unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(uid)
+}
+
+// Binding is a hotkey from the config, and the command it runs
+struct Binding {
+    chord: String,
+    args: RootArgs,
+}
+
+// parse_command turns a command line (without the program name) into arguments to run. Its the
+// same as the normal command line, except that there's no subcommands, and presets come from the
+// daemon's config.
+fn parse_command<I, T>(config: &Config, argv: I) -> Result<RootArgs>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    let mut args = RootArgs::try_parse_from(
+        std::iter::once(OsString::from("mosaic")).chain(argv.into_iter().map(Into::into)),
+    )?;
+    if args.command.is_some() {
+        bail!("subcommands can't be run through the daemon");
+    }
+    apply_preset(&mut args, config)?;
+    check_args(&args)?;
+    Ok(args)
+}
+
+// grab_keys sets up all the hotkeys from the config. A bad binding is an error, so mistakes are
+// obvious at startup, but a key that can't be grabbed (usually because something else has it)
+// just gets a warning, so one conflict doesn't take out all the others.
+fn grab_keys(
+    sess: &Session,
+    config: &Config,
+) -> Result<BTreeMap<(Keycode, Modifiers), Rc<Binding>>> {
+    let mut keys = BTreeMap::new();
+
+    for (chord, cmd) in &config.keys {
+        let kc = chord
+            .parse::<KeyChord>()
+            .map_err(|_| anyhow!("invalid key chord {:?}", chord))?;
+        let args = parse_command(config, cmd.args())
+            .with_context(|| format!("invalid command for key {:?}", chord))?;
+
+        let keycodes = match sess.grab_key(kc.mods, kc.keysym) {
+            Ok(keycodes) => keycodes,
+            Err(e) => {
+                warn!("couldn't grab key {:?}: {}", chord, e);
+                continue;
+            }
+        };
+        if keycodes.is_empty() {
+            warn!("key {:?} isn't on the keyboard, ignoring it", chord);
+            continue;
+        }
+        debug!("grabbed key {:?} as keycodes {:?}", chord, keycodes);
+
+        let binding = Rc::new(Binding {
+            chord: chord.clone(),
+            args,
+        });
+        for keycode in keycodes {
+            keys.insert((keycode, kc.mods), binding.clone());
+        }
+    }
+
+    // XXX if the keyboard mapping changes (MappingNotify) the keycodes could be wrong. we'd have
+    // to ungrab and do it all again. rare enough to not bother for now
+
+    Ok(keys)
+}
+
+// serve runs the daemon. It watches the X server to keep the session up to date, and runs
+// commands from clients and hotkeys as they arrive.
+pub(crate) fn serve(sess: &Session, config: &Config) -> Result<()> {
+    let path = socket_path();
+    make_socket_dir(&path)?;
+
+    if UnixStream::connect(&path).is_ok() {
+        bail!("daemon already running on {}", path.display());
+    }
+    // if the daemon didn't get to clean up last time, there'll be a stale socket in the way
+    let _ = std::fs::remove_file(&path);
+
+    let listener = UnixListener::bind(&path)
+        .with_context(|| format!("couldn't listen on {}", path.display()))?;
+    std::fs::set_permissions(&path, Permissions::from_mode(0o600))?;
+    listener.set_nonblocking(true)?;
+
+    sess.watch().context("failed to watch X11 server")?;
+
+    let keys = grab_keys(sess, config)?;
+
+    info!("listening on {}", path.display());
+
+    loop {
+        // xcb may have already read events off the connection while waiting for something else,
+        // so we have to deal with those first, or poll won't tell us about them. refresh keeps
+        // going until there's nothing queued, including anything that came in while it worked
+        sess.refresh().context("lost connection to X11 server")?;
+
+        // running a command talks to the server, which can read more events, so once we've run
+        // anything we go around again rather than going straight to poll
+        let presses = sess.take_key_presses();
+        if !presses.is_empty() {
+            for press in presses {
+                let Some(binding) = keys.get(&(press.keycode, press.mods)) else {
+                    debug!("ignoring unbound key press {:?}", press);
+                    continue;
+                };
+                debug!("running command for key {:?}", binding.chord);
+                if let Err(e) = run_command(sess, &binding.args) {
+                    warn!("command for key {:?} failed: {:#}", binding.chord, e);
+                }
+            }
+            continue;
+        }
+
+        let mut fds = vec![libc::pollfd {
+            fd: listener.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        }];
+        if let Some(fd) = sess.event_fd() {
+            fds.push(libc::pollfd {
+                fd: fd.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            });
+        }
+        // SAFETY: fds is valid for the duration of the call, and its length is passed correctly
+        if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) } < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() == ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err).context("poll failed");
+        }
+
+        if fds[0].revents == 0 {
+            continue;
+        }
+
+        loop {
+            match listener.accept() {
+                Ok((stream, _)) => handle_client(sess, config, stream),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    warn!("accept failed: {}", e);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+fn handle_client(sess: &Session, config: &Config, mut stream: UnixStream) {
+    // only our own user gets to move our windows around
+    match peer_uid(&stream) {
+        Ok(peer) if peer == uid() => {}
+        Ok(peer) => {
+            warn!("ignoring connection from uid {}", peer);
+            return;
+        }
+        Err(e) => {
+            warn!("couldn't get the client's uid, ignoring it: {}", e);
+            return;
+        }
+    }
+
+    let reply = match run_client_command(sess, config, &mut stream) {
+        Ok(()) => "ok\n".to_string(),
+        Err(e) => {
+            warn!("command failed: {:#}", e);
+            format!("error\n{:#}", e)
+        }
+    };
+    if let Err(e) = stream.write_all(reply.as_bytes()) {
+        warn!("couldn't send reply to client: {}", e);
+    }
+}
+
+fn run_client_command(sess: &Session, config: &Config, stream: &mut UnixStream) -> Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+
+    let mut buf = vec![];
+    stream.read_to_end(&mut buf)?;
+
+    let argv: Vec<_> = match buf.strip_suffix(&[0]) {
+        Some(buf) => buf
+            .split(|&b| b == 0)
+            .map(|arg| OsString::from_vec(arg.to_vec()))
+            .collect(),
+        None => vec![],
+    };
+    debug!("client command: {:?}", argv);
+
+    let args = parse_command(config, argv)?;
+
+    // make sure we're up to date before doing anything
+    sess.refresh()?;
+
+    run_command(sess, &args)
+}
+
+// run_command runs a command, turning a panic into an error, so that one bad command can't take
+// the daemon down with it. the session could have been half way through something when it
+// panicked, so we throw away what it knows and start again
+fn run_command(sess: &Session, args: &RootArgs) -> Result<()> {
+    match std::panic::catch_unwind(AssertUnwindSafe(|| run(sess, args))) {
+        Ok(result) => result,
+        Err(panic) => {
+            sess.invalidate();
+            let msg = panic
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            bail!("command panicked: {}", msg)
+        }
+    }
+}
+
+// forward sends a command line to a running daemon. If there isn't one, returns false, so the
+// caller can do the work itself.
+pub(crate) fn forward(args: impl Iterator<Item = OsString>) -> Result<bool> {
+    let path = socket_path();
+    let mut stream = match UnixStream::connect(&path) {
+        Ok(stream) => stream,
+        Err(e) => {
+            debug!("no daemon on {}: {}", path.display(), e);
+            return Ok(false);
+        }
+    };
+
+    // someone else could be listening there, and it would be handing them our command
+    match peer_uid(&stream) {
+        Ok(peer) if peer == uid() => {}
+        Ok(peer) => {
+            warn!("{} belongs to uid {}, not using it", path.display(), peer);
+            return Ok(false);
+        }
+        Err(e) => {
+            warn!("couldn't get the daemon's uid, not using it: {}", e);
+            return Ok(false);
+        }
+    }
+
+    debug!("forwarding command to daemon on {}", path.display());
+
+    let mut buf = vec![];
+    for arg in args {
+        buf.extend_from_slice(arg.as_bytes());
+        buf.push(0);
+    }
+    stream.write_all(&buf)?;
+    stream.shutdown(Shutdown::Write)?;
+
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+
+    match reply.split_once('\n') {
+        Some(("ok", _)) => Ok(true),
+        Some(("error", msg)) => Err(anyhow!("{}", msg)),
+        _ => bail!("unexpected reply from daemon: {:?}", reply),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn socket_dir_must_be_private() {
+        let dir = std::env::temp_dir().join(format!("mosaic-test-{}", std::process::id()));
+        let path = dir.join("test.sock");
+        let _ = std::fs::remove_dir(&dir);
+
+        make_socket_dir(&path).unwrap();
+        assert_eq!(std::fs::metadata(&dir).unwrap().mode() & 0o777, 0o700);
+        // already there, and ours
+        make_socket_dir(&path).unwrap();
+
+        std::fs::set_permissions(&dir, Permissions::from_mode(0o777)).unwrap();
+        assert!(make_socket_dir(&path).is_err());
+
+        std::fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn peer_is_us() {
+        let (a, b) = UnixStream::pair().unwrap();
+        assert_eq!(peer_uid(&a).unwrap(), uid());
+        assert_eq!(peer_uid(&b).unwrap(), uid());
+    }
+}
@@ -0,0 +1,829 @@
+use crate::geom::*;
+use crate::grid::{cell_geom, GridCell, GridSize};
+use crate::length::{LengthArg, MarginArg, NudgeArg};
+use crate::session::{SizeHints, Strut, WindowState};
+use crate::size::{Size, SizeArg, SizeForRatioArg};
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+// The engine works out where a window goes. It doesn't talk to the X server at all: everything it
+// needs to know about the screen and the window is captured up front into a Screen and a
+// SceneWindow, which can also be saved and loaded as JSON. That's what makes it testable, and
+// lets a misbehaving setup be captured (with --dry-run --format=scene) and replayed.
+
+// Rect is a box as x, y, width and height, which is how people (and X) think about windows
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub(crate) struct Rect {
+    pub x: i16,
+    pub y: i16,
+    pub width: i16,
+    pub height: i16,
+}
+
+impl From<&Box2D> for Rect {
+    fn from(geom: &Box2D) -> Rect {
+        Rect {
+            x: geom.min.x,
+            y: geom.min.y,
+            width: geom.width(),
+            height: geom.height(),
+        }
+    }
+}
+
+impl From<Rect> for Box2D {
+    fn from(r: Rect) -> Box2D {
+        Box2D::from_origin_and_size((r.x, r.y).into(), (r.width, r.height).into())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct SceneMonitor {
+    pub name: String,
+    pub geom: Rect,
+}
+
+// Screen is everything about the screen that placement depends on
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct Screen {
+    // the root window. struts are measured from its edges
+    pub root: Rect,
+    // in the order they're numbered, left to right and then top to bottom
+    pub monitors: Vec<SceneMonitor>,
+    #[serde(default)]
+    pub struts: Vec<Strut>,
+    // _NET_WORKAREA for the current desktop, if the WM sets it
+    #[serde(default)]
+    pub workarea: Option<Rect>,
+    // dock windows, for when there's no struts or workarea to go on
+    #[serde(default)]
+    pub docks: Vec<Rect>,
+    #[serde(default)]
+    pub pointer: Option<(i16, i16)>,
+}
+
+// SceneWindow is what we know about the window being placed
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct SceneWindow {
+    // the window itself, not including the frame, in root window space
+    pub geom: Rect,
+    // frame extents, as top, right, bottom, left. negative for client-side decorations, where
+    // the shadow is part of the window
+    #[serde(default)]
+    pub frame: [i16; 4],
+    #[serde(default)]
+    pub hints: SizeHints,
+}
+
+impl SceneWindow {
+    pub(crate) fn frame(&self) -> SideOffsets2D {
+        let [top, right, bottom, left] = self.frame;
+        SideOffsets2D::new(top, right, bottom, left)
+    }
+
+    // outer is the window's geometry including the frame, which is what all placement works on
+    pub(crate) fn outer(&self) -> Box2D {
+        Box2D::from(self.geom).outer_box(self.frame())
+    }
+}
+
+// how to choose the monitor a window is "on". the selected policy is tried first, and if it can't
+// decide, the others are tried in the order listed here
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub(crate) enum MonitorPolicyArgs {
+    Overlap,
+    Center,
+    Pointer,
+}
+
+// where a window goes in its space when its size hints make it smaller than the space
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub(crate) enum HintsPolicyArgs {
+    // keep to the edge the window is nearest, so it lines up with the screen edge, or its
+    // neighbour in the middle
+    Align,
+    Center,
+    // ignore the hints, and ask for the whole space anyway. the WM will probably shrink it
+    Expand,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) enum MonitorArg {
+    Next,
+    Prev,
+    Index(usize),
+    Name(String),
+}
+
+impl FromStr for MonitorArg {
+    type Err = std::convert::Infallible;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "next" => MonitorArg::Next,
+            "prev" => MonitorArg::Prev,
+            _ => match s.parse::<usize>() {
+                Ok(i) => MonitorArg::Index(i),
+                Err(_) => MonitorArg::Name(s.to_string()),
+            },
+        })
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub(crate) enum HorizAlignArgs {
+    Left,
+    Middle,
+    Right,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub(crate) enum VertAlignArgs {
+    Top,
+    Middle,
+    Bottom,
+}
+
+// Options is everything that decides where a window goes, apart from the screen and the window
+// itself. main fills it in from the command line (and any preset)
+#[derive(Clone, Debug)]
+pub(crate) struct Options {
+    pub monitor_policy: MonitorPolicyArgs,
+    pub hints_policy: HintsPolicyArgs,
+    pub maximize: bool,
+    pub monitor: Option<MonitorArg>,
+    pub halign: Option<HorizAlignArgs>,
+    pub valign: Option<VertAlignArgs>,
+    pub x: Option<Size>,
+    pub y: Option<Size>,
+    pub nudge: Option<NudgeArg>,
+    pub width: Option<Vec<SizeForRatioArg>>,
+    pub height: Option<Vec<SizeForRatioArg>>,
+    pub gap: Option<LengthArg>,
+    pub margin: Option<MarginArg>,
+    pub grid: Option<GridSize>,
+    pub cell: Option<GridCell>,
+    pub span: Option<GridSize>,
+}
+
+// Scene is a screen with a window on it, which is everything needed to place the window
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct Scene {
+    pub screen: Screen,
+    pub window: SceneWindow,
+}
+
+impl Screen {
+    pub(crate) fn monitor_geom(&self, monitor: usize) -> Box2D {
+        self.monitors[monitor].geom.into()
+    }
+
+    // usable_area is the part of a monitor not reserved by panels, according to the hints they and
+    // the window manager provide. None if there are no hints, in which case the caller will have
+    // to work it out some other way
+    fn usable_area(&self, monitor: &Box2D) -> Option<Box2D> {
+        let screen = Box2D::from(self.root);
+
+        let mut usable = None;
+        for (i, strut) in self.struts.iter().enumerate() {
+            let mut area = usable.unwrap_or(*monitor);
+
+            let r = Box2D::new(
+                (screen.min.x, strut.left.start).into(),
+                (
+                    screen.min.x + strut.left.size,
+                    strut.left.end.saturating_add(1),
+                )
+                    .into(),
+            );
+            area = cut_strut(
+                i,
+                area,
+                r,
+                Box2D::new((r.max.x, area.min.y).into(), area.max),
+            );
+
+            let r = Box2D::new(
+                (screen.max.x - strut.right.size, strut.right.start).into(),
+                (screen.max.x, strut.right.end.saturating_add(1)).into(),
+            );
+            area = cut_strut(
+                i,
+                area,
+                r,
+                Box2D::new(area.min, (r.min.x, area.max.y).into()),
+            );
+
+            let r = Box2D::new(
+                (strut.top.start, screen.min.y).into(),
+                (
+                    strut.top.end.saturating_add(1),
+                    screen.min.y + strut.top.size,
+                )
+                    .into(),
+            );
+            area = cut_strut(
+                i,
+                area,
+                r,
+                Box2D::new((area.min.x, r.max.y).into(), area.max),
+            );
+
+            let r = Box2D::new(
+                (strut.bottom.start, screen.max.y - strut.bottom.size).into(),
+                (strut.bottom.end.saturating_add(1), screen.max.y).into(),
+            );
+            area = cut_strut(
+                i,
+                area,
+                r,
+                Box2D::new(area.min, (area.max.x, r.min.y).into()),
+            );
+
+            usable = Some(area);
+        }
+
+        if usable.is_some() {
+            debug!("usable area from struts: {:?}", usable);
+            return usable;
+        }
+
+        // no struts. the window manager might still know something, eg if panels are part of the
+        // WM itself. _NET_WORKAREA is a single box across all monitors though, so the best we can
+        // do is clip it to the one we're interested in
+        let workarea = Box2D::from(self.workarea?);
+        debug!("workarea: {:?}", workarea);
+        let usable = monitor.intersection(&workarea);
+        debug!("usable area from workarea: {:?}", usable);
+        usable
+    }
+
+    // avail_geom is the part of the monitor that windows can be placed in
+    pub(crate) fn avail_geom(&self, monitor: &Box2D) -> Box2D {
+        if let Some(usable) = self.usable_area(monitor) {
+            return usable;
+        }
+
+        // no usable area hints from panels or the WM, so fall back to carving dock windows out of
+        // the monitor
+        debug!("no usable area hints, looking at dock windows");
+        self.docks
+            .iter()
+            .enumerate()
+            .fold(*monitor, |avail, (i, &dock)| {
+                let geom = Box2D::from(dock);
+                debug!("dock {} box: {:?}", i, geom);
+                match avail.intersection(&geom) {
+                    Some(overlap) if overlap == avail => {
+                        debug!("dock {} covers avail area, ignoring it", i);
+                        avail
+                    }
+
+                    // dock doesn't intersect the area, ignore it
+                    None => {
+                        debug!("dock {} is outside avail area, ignoring it", i);
+                        avail
+                    }
+
+                    Some(overlap) => {
+                        debug!(
+                            "dock {} overlaps avail, reducing (overlap {:?})",
+                            i, overlap
+                        );
+
+                        let mut regions = vec![];
+                        if avail.min.x < overlap.min.x {
+                            // left of dock
+                            regions
+                                .push(Box2D::new(avail.min, (overlap.min.x, avail.max.y).into()));
+                        }
+                        if avail.max.x > overlap.max.x {
+                            // right of dock
+                            regions
+                                .push(Box2D::new((overlap.max.x, avail.min.y).into(), avail.max));
+                        }
+                        if avail.min.y < overlap.min.y {
+                            // above dock
+                            regions
+                                .push(Box2D::new(avail.min, (avail.max.x, overlap.min.y).into()));
+                        }
+                        if avail.max.y > overlap.max.y {
+                            // below dock
+                            regions
+                                .push(Box2D::new((avail.min.x, overlap.max.y).into(), avail.max));
+                        }
+
+                        // XXX guaranteed to have one. in some future nonsense, we'd select the "best"
+                        // by some means. I don't do docks really though, so nothing for now
+                        debug!("new avail regions (taking last): {:?}", regions);
+                        regions.pop().unwrap()
+                    }
+                }
+            })
+    }
+
+    // select_monitor finds the monitor the window is "on", returning its index in the monitor list
+    pub(crate) fn select_monitor(&self, geom: &Box2D, policy: MonitorPolicyArgs) -> Option<usize> {
+        let policies = std::iter::once(policy).chain(
+            MonitorPolicyArgs::value_variants()
+                .iter()
+                .copied()
+                .filter(|&p| p != policy),
+        );
+
+        for policy in policies {
+            let monitor = match policy {
+                MonitorPolicyArgs::Overlap => {
+                    let mut overlaps: Vec<_> = self
+                        .monitors
+                        .iter()
+                        .enumerate()
+                        .filter_map(|(i, m)| {
+                            let overlap = Box2D::from(m.geom).intersection(geom)?;
+                            let area = overlap.width() as i32 * overlap.height() as i32;
+                            debug!("monitor {} overlap area: {}", m.name, area);
+                            Some((area, i))
+                        })
+                        .collect();
+                    overlaps.sort_by_key(|&(area, _)| std::cmp::Reverse(area));
+                    match overlaps.as_slice() {
+                        [] => None,
+                        // a tie means the window is split evenly, so we can't tell
+                        [(a, _), (b, _), ..] if a == b => None,
+                        [(_, i), ..] => Some(*i),
+                    }
+                }
+                MonitorPolicyArgs::Center => self.monitor_at(geom.center()),
+                MonitorPolicyArgs::Pointer => match self.pointer {
+                    Some(pointer) => {
+                        debug!("pointer position: {:?}", pointer);
+                        self.monitor_at(pointer.into())
+                    }
+                    None => {
+                        debug!("don't know where the pointer is");
+                        None
+                    }
+                },
+            };
+
+            match monitor {
+                Some(i) => {
+                    debug!("monitor policy {:?} selected monitor {}", policy, i);
+                    return Some(i);
+                }
+                None => debug!("monitor policy {:?} couldn't select a monitor", policy),
+            }
+        }
+
+        None
+    }
+
+    // monitor_at finds the monitor containing the point
+    pub(crate) fn monitor_at(&self, point: Point2D) -> Option<usize> {
+        self.monitors
+            .iter()
+            .position(|m| Box2D::from(m.geom).contains(point))
+    }
+
+    // resolve_monitor finds the monitor requested by --monitor, relative to the one the window is on
+    fn resolve_monitor(&self, current: usize, arg: &MonitorArg) -> Option<usize> {
+        let count = self.monitors.len();
+        match arg {
+            MonitorArg::Next => Some((current + 1) % count),
+            MonitorArg::Prev => Some((current + count - 1) % count),
+            MonitorArg::Index(i) => (*i < count).then_some(*i),
+            MonitorArg::Name(name) => self.monitors.iter().position(|m| m.name == *name),
+        }
+    }
+}
+
+// reduce an area to exclude a region reserved by a strut. the region only matters if it overlaps
+// the area, and is ignored if it would cover the whole thing (which happens on multihead, when a
+// panel on an inner edge has to reserve the entire monitor beside it)
+fn cut_strut(i: usize, area: Box2D, reserved: Box2D, reduced: Box2D) -> Box2D {
+    if reserved.is_empty() || !reserved.intersects(&area) {
+        return area;
+    }
+    match reduced.intersection(&area) {
+        Some(reduced) if !reduced.is_empty() => {
+            debug!(
+                "strut {} reserves {:?}, reducing {:?} to {:?}",
+                i, reserved, area, reduced
+            );
+            reduced
+        }
+        _ => {
+            debug!(
+                "strut {} reserves {:?}, which covers all of {:?}, ignoring it",
+                i, reserved, area
+            );
+            area
+        }
+    }
+}
+
+// Located is where a window is, and the area its going to be placed within
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Located {
+    // the window's geometry (including the frame) to work from. its where the window is now,
+    // unless its moving to another monitor, where its mapped into the new one
+    pub current: Box2D,
+    pub avail: Box2D,
+}
+
+// locate finds the monitor the window is on, or the one its moving to, and works out the
+// available area there
+pub(crate) fn locate(screen: &Screen, window: &SceneWindow, args: &Options) -> Result<Located> {
+    let current = window.outer();
+
+    let monitor = screen
+        .select_monitor(&current, args.monitor_policy)
+        .context("couldn't determine which monitor contains window")?;
+
+    let monitor_geom = screen.monitor_geom(monitor);
+    debug!("monitor {} geom: {:?}", monitor, monitor_geom);
+
+    let avail = screen.avail_geom(&monitor_geom);
+    debug!("avail geom: {:?}", avail);
+
+    let Some(ref arg) = args.monitor else {
+        return Ok(Located { current, avail });
+    };
+
+    // moving to another monitor. we find its avail area, and map the window's current geometry
+    // into it, so anything not being changed keeps the same relative position and size
+    let target = screen
+        .resolve_monitor(monitor, arg)
+        .with_context(|| format!("couldn't find monitor {:?}", arg))?;
+
+    let target_geom = screen.monitor_geom(target);
+    debug!("target monitor {} geom: {:?}", target, target_geom);
+
+    let target_avail = screen.avail_geom(&target_geom);
+    debug!("target avail geom: {:?}", target_avail);
+
+    let mapped = map_geom(&current, &avail, &target_avail);
+    debug!("target mapped geom: {:?}", mapped);
+
+    Ok(Located {
+        current: mapped,
+        avail: target_avail,
+    })
+}
+
+// Placed is where a window is going: its geometry including the frame, the geometry of the
+// window itself (which is what we ask the WM for), and any maximized state to set
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Placed {
+    pub outer: Box2D,
+    pub client: Box2D,
+    pub maximize: WindowState,
+}
+
+// place works out the new geometry for a single window
+pub(crate) fn place(window: &SceneWindow, located: &Located, args: &Options) -> Placed {
+    let geom = compute_new_geom(&located.current, &located.avail, &window.hints, args);
+    debug!("computed new geom: {:?}", geom);
+    finish(&geom, window, &located.avail, args)
+}
+
+// finish takes the space a window has been given (including the frame), and works out what it
+// will really get: it fits it to the window's size hints, and takes off the frame
+pub(crate) fn finish(geom: &Box2D, window: &SceneWindow, avail: &Box2D, args: &Options) -> Placed {
+    // maximize is decided on the space we were asked to fill, not what the size hints let the
+    // window have, because the WM will apply the hints itself
+    let mut maximize = WindowState::empty();
+    if args.maximize {
+        maximize.set(
+            WindowState::MAXIMIZED_HORZ,
+            geom.min.x == avail.min.x && geom.max.x == avail.max.x,
+        );
+        maximize.set(
+            WindowState::MAXIMIZED_VERT,
+            geom.min.y == avail.min.y && geom.max.y == avail.max.y,
+        );
+    }
+
+    let outer = fit_hints(geom, window, avail, args.hints_policy);
+    let client = outer.inner_box(window.frame());
+    debug!("new client geom: {:?}", client);
+
+    Placed {
+        outer,
+        client,
+        maximize,
+    }
+}
+
+// fit_hints shrinks the geometry (including the frame) to a size the client will accept, and
+// places it in the space according to the policy
+fn fit_hints(geom: &Box2D, window: &SceneWindow, avail: &Box2D, policy: HintsPolicyArgs) -> Box2D {
+    if policy == HintsPolicyArgs::Expand {
+        return *geom;
+    }
+
+    let client = geom.inner_box(window.frame());
+    let (w, h) = window.hints.fit((client.width(), client.height()));
+    let (w, h) = (
+        w + geom.width() - client.width(),
+        h + geom.height() - client.height(),
+    );
+    if (w, h) == (geom.width(), geom.height()) {
+        return *geom;
+    }
+
+    // for align, the window keeps the edge on the same side of the area as it is, or stays in
+    // the middle if its right in the middle
+    let pos = |min: i16, len: i16, new_len: i16, center: i16, avail_center: i16| {
+        let leftover = len - new_len;
+        match policy {
+            HintsPolicyArgs::Align if center < avail_center => min,
+            HintsPolicyArgs::Align if center > avail_center => min + leftover,
+            _ => min + leftover.div_euclid(2),
+        }
+    };
+    let x = pos(
+        geom.min.x,
+        geom.width(),
+        w,
+        geom.center().x,
+        avail.center().x,
+    );
+    let y = pos(
+        geom.min.y,
+        geom.height(),
+        h,
+        geom.center().y,
+        avail.center().y,
+    );
+
+    let fitted = Box2D::from_origin_and_size((x, y).into(), (w, h).into());
+    debug!("fitted {:?} to size hints: {:?}", geom, fitted);
+    fitted
+}
+
+// scale a box in one area to the same relative position and size in another. an empty area (a
+// monitor thats all struts, say) has nothing to scale by, so the box goes in the corner of the
+// new one, no bigger than it
+fn map_geom(geom: &Box2D, from: &Box2D, to: &Box2D) -> Box2D {
+    let scale = |v: i16, from_min: i16, from_len: i16, to_min: i16, to_len: i16| match from_len {
+        ..=0 => to_min,
+        _ => to_min + ((v - from_min) as i32 * to_len as i32 / from_len as i32) as i16,
+    };
+    let scale_len = |v: i16, from_len: i16, to_len: i16| match from_len {
+        ..=0 => v.min(to_len),
+        _ => (v as i32 * to_len as i32 / from_len as i32) as i16,
+    };
+    Box2D::from_origin_and_size(
+        (
+            scale(geom.min.x, from.min.x, from.width(), to.min.x, to.width()),
+            scale(geom.min.y, from.min.y, from.height(), to.min.y, to.height()),
+        )
+            .into(),
+        (
+            scale_len(geom.width(), from.width(), to.width()),
+            scale_len(geom.height(), from.height(), to.height()),
+        )
+            .into(),
+    )
+}
+
+// compute_new_geom works out the space the window should have. the size hints don't limit it (that's
+// for finish), but they say how far off the window can be from where it was last put
+pub(crate) fn compute_new_geom(
+    current: &Box2D,
+    avail: &Box2D,
+    hints: &SizeHints,
+    args: &Options,
+) -> Box2D {
+    // the ratio is the shape of the whole area, before any margin
+    let ratio = avail.width() as f32 / avail.height() as f32;
+
+    let (avail, gaps) = gaps_and_margin(avail, args.gap, args.margin);
+    debug!("avail geom inside margin: {:?}, gaps {:?}", avail, gaps);
+    let avail = &avail;
+
+    if let Some(grid) = args.grid {
+        let cell = args.cell.unwrap_or(GridCell { col: 0, row: 0 });
+        let span = args.span.unwrap_or(GridSize { cols: 1, rows: 1 });
+        debug!("grid {:?} cell {:?} span {:?}", grid, cell, span);
+        let geom = gap_box(&cell_geom(avail, grid, cell, span), avail, gaps);
+        return nudge_geom(&geom, avail, args.nudge);
+    }
+
+    debug!(
+        "ratio {} halign {:?} valign {:?} x {:?} y {:?} width {:?} height {:?}",
+        ratio, args.halign, args.valign, args.x, args.y, args.width, args.height
+    );
+
+    let x_for = |w: i16| match (args.halign, args.x) {
+        (Some(p), _) => match p {
+            HorizAlignArgs::Left => avail.min.x,
+            HorizAlignArgs::Middle => avail.min.x + (avail.width() - w).div_euclid(2),
+            HorizAlignArgs::Right => avail.max.x - w,
+        },
+        (None, Some(x)) => avail.min.x + x.offset(avail.width(), current.min.x - avail.min.x),
+        (None, None) => current.min.x,
+    };
+
+    let y_for = |h: i16| match (args.valign, args.y) {
+        (Some(p), _) => match p {
+            VertAlignArgs::Top => avail.min.y,
+            VertAlignArgs::Middle => avail.min.y + (avail.height() - h).div_euclid(2),
+            VertAlignArgs::Bottom => avail.max.y - h,
+        },
+        (None, Some(y)) => avail.min.y + y.offset(avail.height(), current.min.y - avail.min.y),
+        (None, None) => current.min.y,
+    };
+
+    // where a window of the given length goes, less any gap on the sides that aren't at the edge
+    // of the area. gaps only apply along an axis with both an alignment and a size rule, because
+    // they're computed fresh each time. otherwise the window would shrink or creep a little every
+    // time it was placed
+    let place_x = |w: i16| match args.halign {
+        Some(_) => gap_span(x_for(w), w, avail.min.x, avail.max.x, gaps.0),
+        None => (x_for(w), w),
+    };
+    let place_y = |h: i16| match args.valign {
+        Some(_) => gap_span(y_for(h), h, avail.min.y, avail.max.y, gaps.1),
+        None => (y_for(h), h),
+    };
+
+    let (x, w) = args
+        .width
+        .as_ref()
+        .and_then(|v| v.iter().find(|pc| pc.matches(ratio)))
+        .map_or_else(
+            || (x_for(current.width()), current.width()),
+            |pc| {
+                debug!("selected width rule {:?} for ratio {}", pc, ratio);
+                place_x(select_size(
+                    &pc.value,
+                    avail.width(),
+                    current.min.x,
+                    current.width(),
+                    cycle_tolerance(hints.inc.map(|(w, _)| w)),
+                    place_x,
+                ))
+            },
+        );
+
+    let (y, h) = args
+        .height
+        .as_ref()
+        .and_then(|v| v.iter().find(|pc| pc.matches(ratio)))
+        .map_or_else(
+            || (y_for(current.height()), current.height()),
+            |pc| {
+                debug!("selected height rule {:?} for ratio {}", pc, ratio);
+                place_y(select_size(
+                    &pc.value,
+                    avail.height(),
+                    current.min.y,
+                    current.height(),
+                    cycle_tolerance(hints.inc.map(|(_, h)| h)),
+                    place_y,
+                ))
+            },
+        );
+
+    let geom = Box2D::from_origin_and_size((x, y).into(), (w, h).into());
+    nudge_geom(&geom, avail, args.nudge)
+}
+
+// nudge_geom moves the box by the nudge, if there is one. it stops at the edge of the area, so
+// nudging over and over doesn't push the window off the screen. a window bigger than the area
+// can still go as far as its near edge
+fn nudge_geom(geom: &Box2D, area: &Box2D, nudge: Option<NudgeArg>) -> Box2D {
+    let Some(nudge) = nudge else {
+        return *geom;
+    };
+    let slide = |pos: i16, len: i16, d: i16, min: i16, max: i16| {
+        let to = pos + d;
+        match d.signum() {
+            1 => to.min((max - len).max(pos)),
+            -1 => to.max(min.min(pos)),
+            _ => pos,
+        }
+    };
+    let x = slide(
+        geom.min.x,
+        geom.width(),
+        nudge.dx.of(area.width()),
+        area.min.x,
+        area.max.x,
+    );
+    let y = slide(
+        geom.min.y,
+        geom.height(),
+        nudge.dy.of(area.height()),
+        area.min.y,
+        area.max.y,
+    );
+    debug!("nudged {:?} to {},{}", geom, x, y);
+    Box2D::from_origin_and_size((x, y).into(), geom.size())
+}
+
+// gaps_and_margin resolves the gap and margin options against the available area, returning the
+// area inside the margin, and the horizontal and vertical gaps
+pub(crate) fn gaps_and_margin(
+    avail: &Box2D,
+    gap: Option<LengthArg>,
+    margin: Option<MarginArg>,
+) -> (Box2D, (i16, i16)) {
+    let area = match margin {
+        Some(m) => avail.inner_box(m.offsets(avail)),
+        None => *avail,
+    };
+    let gaps = gap.map_or((0, 0), |g| (g.of(area.width()), g.of(area.height())));
+    (area, gaps)
+}
+
+// gap_span takes half the gap off each end of a span (pos, len) that isn't at the edge of the
+// area, so neighbours end up a whole gap apart. for an odd gap the extra pixel comes off the
+// start, so the two halves still add up
+fn gap_span(pos: i16, len: i16, area_min: i16, area_max: i16, gap: i16) -> (i16, i16) {
+    let before = if pos > area_min { gap - gap / 2 } else { 0 };
+    let after = if pos + len < area_max { gap / 2 } else { 0 };
+    (pos + before, len - before - after)
+}
+
+// gap_box is gap_span on both axes
+pub(crate) fn gap_box(geom: &Box2D, area: &Box2D, gaps: (i16, i16)) -> Box2D {
+    let (x, w) = gap_span(geom.min.x, geom.width(), area.min.x, area.max.x, gaps.0);
+    let (y, h) = gap_span(geom.min.y, geom.height(), area.min.y, area.max.y, gaps.1);
+    Box2D::from_origin_and_size((x, y).into(), (w, h).into())
+}
+
+pub(crate) fn percent_of(pc: i32, len: i16) -> i16 {
+    match pc {
+        0 => 1,
+        100 => len,
+        pc => (len as i32 * pc).div_euclid(100) as i16,
+    }
+}
+
+// how far the window can be from a cycle entry and still be considered to be at it, when the WM
+// has adjusted the window a little on its own
+const CYCLE_TOLERANCE: i16 = 16;
+
+// cycle_tolerance is CYCLE_TOLERANCE, or more for a window with a size increment. fitting to the
+// increment (eg a terminal's character cells) can take up to one less than a whole increment off
+// the length, and move the window by as much
+fn cycle_tolerance(inc: Option<i16>) -> i16 {
+    CYCLE_TOLERANCE.max(inc.unwrap_or(1) - 1)
+}
+
+// select_size works out a window length along one axis from a size rule. for a cycle, we find the
+// entry the window is currently at (both length and position, since the same size at the other
+// end of the screen is a different layout) and step to the one after it. if the window isn't at
+// any of them, we start at the beginning. place gives the final position and length for a length,
+// which is what the window will actually be at
+fn select_size(
+    arg: &SizeArg,
+    avail_len: i16,
+    cur_min: i16,
+    cur_len: i16,
+    tolerance: i16,
+    place: impl Fn(i16) -> (i16, i16),
+) -> i16 {
+    match arg {
+        SizeArg::Fixed(size) => size.of(avail_len, cur_len),
+        SizeArg::Cycle(sizes) => {
+            let current = sizes.iter().position(|size| {
+                let (pos, len) = place(size.of(avail_len, cur_len));
+                (len - cur_len).abs() <= tolerance && (pos - cur_min).abs() <= tolerance
+            });
+            let next = current.map_or(0, |i| (i + 1) % sizes.len());
+            debug!("cycle {:?} at {:?}, selecting {}", sizes, current, next);
+            sizes[next].of(avail_len, cur_len)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn map_empty_area() {
+        let geom = Box2D::from_origin_and_size((100, 100).into(), (800, 600).into());
+        let from = Box2D::from_origin_and_size((0, 0).into(), (1920, 1080).into());
+        let to = Box2D::from_origin_and_size((1920, 0).into(), (2560, 1440).into());
+        assert_eq!(
+            map_geom(&geom, &from, &to),
+            Box2D::from_origin_and_size((2053, 133).into(), (1066, 800).into())
+        );
+
+        // no height to scale from, so it goes at the top, and no taller than the new area
+        let empty = Box2D::from_origin_and_size((0, 40).into(), (1920, 0).into());
+        let small = Box2D::from_origin_and_size((1920, 0).into(), (1024, 500).into());
+        assert_eq!(
+            map_geom(&geom, &empty, &small),
+            Box2D::from_origin_and_size((1973, 0).into(), (426, 500).into())
+        );
+    }
+}
@@ -0,0 +1,281 @@
+use crate::backend::{Backend, Event, WindowInfo};
+use crate::geom::*;
+use crate::keys::{Keycode, Modifiers};
+use crate::session::{HistoryEntry, Monitor, SizeHints, Strut, WindowState, WindowType};
+
+use anyhow::{Context, Result};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::os::fd::BorrowedFd;
+
+const ROOT: u32 = 1;
+
+// Fake is an in-memory backend, for testing without an X server. it plays the part of the WM
+// too, like a simple non-reparenting one: every window is a child of the root, and anything asked
+// of it happens straight away. each change is reported as an event, so a session can refresh to
+// catch up, same as a watching one would
+pub(crate) struct Fake {
+    root: Box2D,
+    monitors: Vec<Monitor>,
+    current_desktop: Option<u32>,
+    desktop_count: Option<u32>,
+    pointer: Point2D,
+    active: Option<u32>,
+    // the window the user will pick for select_window
+    selected: Option<u32>,
+    windows: RefCell<BTreeMap<u32, FakeWindow>>,
+    events: RefCell<Vec<Event>>,
+}
+
+// FakeWindow is a window, and everything about it a backend can be asked for
+#[derive(Clone, Debug)]
+pub(crate) struct FakeWindow {
+    // the window itself, not including the frame
+    pub geom: Box2D,
+    pub typ: WindowType,
+    pub frame: SideOffsets2D,
+    pub state: WindowState,
+    pub desktop: Option<u32>,
+    pub strut: Option<Strut>,
+    pub name: String,
+    pub class: Option<(String, String)>,
+    pub role: Option<String>,
+    pub pid: Option<u32>,
+    pub hints: SizeHints,
+    pub viewable: bool,
+    pub history: Vec<HistoryEntry>,
+}
+
+impl FakeWindow {
+    // normal is an ordinary visible application window, with a plain frame
+    pub(crate) fn normal(geom: Box2D) -> FakeWindow {
+        FakeWindow {
+            geom,
+            typ: WindowType::Normal,
+            frame: SideOffsets2D::new(20, 1, 1, 1),
+            state: WindowState::empty(),
+            desktop: Some(0),
+            strut: None,
+            name: String::new(),
+            class: None,
+            role: None,
+            pid: None,
+            hints: SizeHints::default(),
+            viewable: true,
+            history: vec![],
+        }
+    }
+
+    // dock is a panel, without a frame
+    pub(crate) fn dock(geom: Box2D, strut: Option<Strut>) -> FakeWindow {
+        FakeWindow {
+            typ: WindowType::Dock,
+            frame: SideOffsets2D::zero(),
+            desktop: None,
+            strut,
+            ..FakeWindow::normal(geom)
+        }
+    }
+}
+
+impl Fake {
+    pub(crate) fn new(root: Box2D) -> Fake {
+        Fake {
+            root,
+            monitors: vec![],
+            current_desktop: None,
+            desktop_count: None,
+            pointer: root.min,
+            active: None,
+            selected: None,
+            windows: RefCell::new(BTreeMap::new()),
+            events: RefCell::new(vec![]),
+        }
+    }
+
+    pub(crate) fn add_monitor(&mut self, name: &str, geom: Box2D) {
+        self.monitors.push(Monitor {
+            name: name.to_string(),
+            geom,
+        });
+    }
+
+    pub(crate) fn set_desktops(&mut self, current: u32, count: u32) {
+        self.current_desktop = Some(current);
+        self.desktop_count = Some(count);
+    }
+
+    pub(crate) fn set_pointer(&mut self, pointer: Point2D) {
+        self.pointer = pointer;
+    }
+
+    pub(crate) fn set_active(&mut self, id: u32) {
+        self.active = Some(id);
+    }
+
+    pub(crate) fn set_selected(&mut self, id: u32) {
+        self.selected = Some(id);
+    }
+
+    // add_window puts a new window on the screen, returning its id. ids are handed out in order,
+    // so the first window added is the oldest
+    pub(crate) fn add_window(&mut self, w: FakeWindow) -> u32 {
+        let mut windows = self.windows.borrow_mut();
+        let id = windows.keys().last().map_or(0x400001, |&id| id + 1);
+        windows.insert(id, w);
+        id
+    }
+
+    fn info(&self, id: u32, w: &FakeWindow) -> WindowInfo {
+        WindowInfo {
+            id,
+            parent: ROOT,
+            children: vec![],
+            geom: w.geom,
+            typ: w.typ,
+            // the WM would only set WM_STATE on the normal windows it manages
+            selectable: w.typ == WindowType::Normal && w.viewable,
+            strut: w.strut,
+            desktop: w.desktop,
+        }
+    }
+
+    fn with_window<T>(&self, id: u32, f: impl FnOnce(&mut FakeWindow) -> T) -> Result<T> {
+        let mut windows = self.windows.borrow_mut();
+        let w = windows
+            .get_mut(&id)
+            .with_context(|| format!("no such window {}", id))?;
+        Ok(f(w))
+    }
+}
+
+impl Backend for Fake {
+    fn root(&self) -> u32 {
+        ROOT
+    }
+
+    fn load_windows(&self) -> Vec<WindowInfo> {
+        let windows = self.windows.borrow();
+        let root = WindowInfo {
+            id: ROOT,
+            parent: ROOT,
+            children: windows.keys().copied().collect(),
+            geom: self.root,
+            typ: WindowType::Root,
+            selectable: false,
+            strut: None,
+            desktop: None,
+        };
+        std::iter::once(root)
+            .chain(windows.iter().map(|(&id, w)| self.info(id, w)))
+            .collect()
+    }
+
+    fn load_window(&self, id: u32, _parent: u32) -> Option<WindowInfo> {
+        self.windows.borrow().get(&id).map(|w| self.info(id, w))
+    }
+
+    fn monitors(&self) -> Result<Vec<Monitor>> {
+        Ok(self.monitors.clone())
+    }
+
+    fn current_desktop(&self) -> Option<u32> {
+        self.current_desktop
+    }
+
+    fn desktop_count(&self) -> Option<u32> {
+        self.desktop_count
+    }
+
+    // struts are enough for the fake WM
+    fn workarea(&self) -> Option<Box2D> {
+        None
+    }
+
+    fn active_window(&self) -> Result<u32> {
+        Ok(self.active.unwrap_or(0))
+    }
+
+    fn select_window(&self) -> Result<u32> {
+        self.selected.context("nothing selected")
+    }
+
+    fn pointer(&self) -> Result<Point2D> {
+        Ok(self.pointer)
+    }
+
+    fn frame_extents(&self, id: u32) -> Result<SideOffsets2D> {
+        self.with_window(id, |w| w.frame)
+    }
+
+    fn state(&self, id: u32) -> Result<WindowState> {
+        self.with_window(id, |w| w.state)
+    }
+
+    fn history(&self, id: u32) -> Result<Vec<HistoryEntry>> {
+        self.with_window(id, |w| w.history.clone())
+    }
+
+    fn name(&self, id: u32) -> Result<String> {
+        self.with_window(id, |w| w.name.clone())
+    }
+
+    fn class(&self, id: u32) -> Result<Option<(String, String)>> {
+        self.with_window(id, |w| w.class.clone())
+    }
+
+    fn role(&self, id: u32) -> Result<Option<String>> {
+        self.with_window(id, |w| w.role.clone())
+    }
+
+    fn pid(&self, id: u32) -> Result<Option<u32>> {
+        self.with_window(id, |w| w.pid)
+    }
+
+    fn is_viewable(&self, id: u32) -> Result<bool> {
+        self.with_window(id, |w| w.viewable)
+    }
+
+    fn size_hints(&self, id: u32) -> Result<SizeHints> {
+        self.with_window(id, |w| w.hints)
+    }
+
+    fn set_geom(&self, id: u32, geom: &Box2D) -> Result<()> {
+        self.with_window(id, |w| w.geom = *geom)?;
+        self.events
+            .borrow_mut()
+            .push(Event::Configured { id, geom: *geom });
+        Ok(())
+    }
+
+    fn set_state(&self, id: u32, state: WindowState, on: bool) -> Result<()> {
+        self.with_window(id, |w| w.state.set(state, on))
+    }
+
+    fn set_desktop(&self, id: u32, desktop: u32) -> Result<()> {
+        self.with_window(id, |w| w.desktop = Some(desktop))?;
+        self.events.borrow_mut().push(Event::Changed(id));
+        Ok(())
+    }
+
+    fn set_history(&self, id: u32, history: &[HistoryEntry]) -> Result<()> {
+        self.with_window(id, |w| w.history = history.to_vec())
+    }
+
+    fn watch(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn poll_events(&self) -> Result<Vec<Event>> {
+        Ok(self.events.take())
+    }
+
+    // events only come from what the tests do, so there's never anything to wait for
+    fn event_fd(&self) -> Option<BorrowedFd<'_>> {
+        None
+    }
+
+    fn grab_key(&self, _mods: Modifiers, _keysym: u32) -> Result<Vec<Keycode>> {
+        Ok(vec![])
+    }
+}
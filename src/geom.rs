@@ -1,3 +1,4 @@
 pub struct RootSpace;
 pub type Box2D = euclid::Box2D<i16, RootSpace>;
 pub type SideOffsets2D = euclid::SideOffsets2D<i16, RootSpace>;
+pub type Point2D = euclid::Point2D<i16, RootSpace>;
@@ -0,0 +1,70 @@
+use crate::geom::*;
+
+use std::str::FromStr;
+
+// GridSize is a number of columns and rows, as COLSxROWS. It's used for both the grid itself and
+// for how many cells a window spans.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct GridSize {
+    pub cols: u16,
+    pub rows: u16,
+}
+
+// GridCell is a zero-based column and row, as COL,ROW
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct GridCell {
+    pub col: u16,
+    pub row: u16,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct GridParseError;
+
+impl FromStr for GridSize {
+    type Err = GridParseError;
+    fn from_str(s: &str) -> Result<Self, GridParseError> {
+        let (c, r) = s.split_once('x').ok_or(GridParseError)?;
+        let cols = c.parse::<u16>().map_err(|_| GridParseError)?;
+        let rows = r.parse::<u16>().map_err(|_| GridParseError)?;
+        if cols == 0 || rows == 0 {
+            return Err(GridParseError);
+        }
+        Ok(GridSize { cols, rows })
+    }
+}
+
+impl FromStr for GridCell {
+    type Err = GridParseError;
+    fn from_str(s: &str) -> Result<Self, GridParseError> {
+        let (c, r) = s.split_once(',').ok_or(GridParseError)?;
+        Ok(GridCell {
+            col: c.parse::<u16>().map_err(|_| GridParseError)?,
+            row: r.parse::<u16>().map_err(|_| GridParseError)?,
+        })
+    }
+}
+
+// grid_line is the position of line n of a length split into parts. we compute each line
+// independently from the start rather than adding up part sizes, so the remainder pixels get
+// spread across the parts and the last line always lands exactly on the end
+pub(crate) fn grid_line(min: i16, len: i16, parts: u16, n: u16) -> i16 {
+    min + (len as i32 * n as i32 / parts as i32) as i16
+}
+
+// cell_geom is the box covered by a cell and the cells it spans, within the given area
+pub(crate) fn cell_geom(area: &Box2D, grid: GridSize, cell: GridCell, span: GridSize) -> Box2D {
+    let col_end = cell.col.saturating_add(span.cols).min(grid.cols);
+    let row_end = cell.row.saturating_add(span.rows).min(grid.rows);
+    Box2D::new(
+        (
+            grid_line(area.min.x, area.width(), grid.cols, cell.col),
+            grid_line(area.min.y, area.height(), grid.rows, cell.row),
+        )
+            .into(),
+        (
+            grid_line(area.min.x, area.width(), grid.cols, col_end),
+            grid_line(area.min.y, area.height(), grid.rows, row_end),
+        )
+            .into(),
+    )
+}
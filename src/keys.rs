@@ -0,0 +1,203 @@
+bitflags::bitflags! {
+    // Modifiers are the modifier keys held down with a key. the bits are the same as X's modifier
+    // mask, since that's where they mostly end up
+    #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+    pub struct Modifiers: u32 {
+        const SHIFT   = 1 << 0;
+        const LOCK    = 1 << 1;
+        const CONTROL = 1 << 2;
+        const MOD1    = 1 << 3;
+        const MOD2    = 1 << 4;
+        const MOD3    = 1 << 5;
+        const MOD4    = 1 << 6;
+        const MOD5    = 1 << 7;
+    }
+}
+
+// Keycode is a physical key on the keyboard, numbered however the backend numbers them. keysyms
+// are what's printed on them
+pub(crate) type Keycode = u32;
+
+// KeyChord is a key and the modifiers that have to be held with it, written like
+// "Ctrl+Super+Left". Modifier and key names are case-insensitive. Keys are X keysym names (the
+// common ones, anyway), or a raw keysym as 0x....
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct KeyChord {
+    pub mods: Modifiers,
+    pub keysym: u32,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct KeyChordParseError;
+
+impl std::str::FromStr for KeyChord {
+    type Err = KeyChordParseError;
+    fn from_str(s: &str) -> Result<Self, KeyChordParseError> {
+        let mut parts: Vec<_> = s.split('+').map(str::trim).collect();
+        let key = parts.pop().ok_or(KeyChordParseError)?;
+
+        let mods = parts.iter().try_fold(Modifiers::empty(), |mods, m| {
+            Ok(mods | parse_modifier(m).ok_or(KeyChordParseError)?)
+        })?;
+        let keysym = parse_keysym(key).ok_or(KeyChordParseError)?;
+
+        Ok(KeyChord { mods, keysym })
+    }
+}
+
+fn parse_modifier(s: &str) -> Option<Modifiers> {
+    Some(match s.to_ascii_lowercase().as_str() {
+        "shift" => Modifiers::SHIFT,
+        "ctrl" | "control" | "primary" => Modifiers::CONTROL,
+        "alt" | "mod1" => Modifiers::MOD1,
+        "mod2" => Modifiers::MOD2,
+        "mod3" => Modifiers::MOD3,
+        "super" | "win" | "mod4" => Modifiers::MOD4,
+        "mod5" => Modifiers::MOD5,
+        _ => return None,
+    })
+}
+
+// names of the keysyms people are likely to want, from X11/keysymdef.h
+const KEYSYMS: &[(&str, u32)] = &[
+    ("space", 0x0020),
+    ("apostrophe", 0x0027),
+    ("comma", 0x002c),
+    ("minus", 0x002d),
+    ("period", 0x002e),
+    ("slash", 0x002f),
+    ("semicolon", 0x003b),
+    ("equal", 0x003d),
+    ("bracketleft", 0x005b),
+    ("backslash", 0x005c),
+    ("bracketright", 0x005d),
+    ("grave", 0x0060),
+    ("backspace", 0xff08),
+    ("tab", 0xff09),
+    ("return", 0xff0d),
+    ("enter", 0xff0d),
+    ("pause", 0xff13),
+    ("escape", 0xff1b),
+    ("home", 0xff50),
+    ("left", 0xff51),
+    ("up", 0xff52),
+    ("right", 0xff53),
+    ("down", 0xff54),
+    ("prior", 0xff55),
+    ("pageup", 0xff55),
+    ("next", 0xff56),
+    ("pagedown", 0xff56),
+    ("end", 0xff57),
+    ("print", 0xff61),
+    ("insert", 0xff63),
+    ("kp_enter", 0xff8d),
+    ("kp_home", 0xff95),
+    ("kp_left", 0xff96),
+    ("kp_up", 0xff97),
+    ("kp_right", 0xff98),
+    ("kp_down", 0xff99),
+    ("kp_prior", 0xff9a),
+    ("kp_next", 0xff9b),
+    ("kp_end", 0xff9c),
+    ("kp_begin", 0xff9d),
+    ("delete", 0xffff),
+];
+
+fn parse_keysym(s: &str) -> Option<u32> {
+    if let Some(hex) = s.strip_prefix("0x") {
+        return u32::from_str_radix(hex, 16).ok();
+    }
+
+    let name = s.to_ascii_lowercase();
+
+    // letters and digits are their own ASCII codes. X uses the lowercase keysym for the key
+    // itself, and Shift gives the uppercase, so "A" means the A key
+    if let [c] = name.as_bytes()
+        && c.is_ascii_alphanumeric()
+    {
+        return Some(*c as u32);
+    }
+
+    // F1-F35 and KP_0-KP_9 are contiguous
+    if let Some(n) = name.strip_prefix('f').and_then(|n| n.parse::<u32>().ok())
+        && (1..=35).contains(&n)
+    {
+        return Some(0xffbe + n - 1);
+    }
+    if let Some(n) = name.strip_prefix("kp_").and_then(|n| n.parse::<u32>().ok())
+        && n <= 9
+    {
+        return Some(0xffb0 + n);
+    }
+
+    KEYSYMS
+        .iter()
+        .find_map(|&(n, keysym)| (n == name).then_some(keysym))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chord(s: &str) -> Result<KeyChord, KeyChordParseError> {
+        s.parse()
+    }
+
+    #[test]
+    fn chords() {
+        assert_eq!(
+            chord("Ctrl+Super+Left"),
+            Ok(KeyChord {
+                mods: Modifiers::CONTROL | Modifiers::MOD4,
+                keysym: 0xff51,
+            })
+        );
+        // names are case-insensitive, and spaces around the + don't matter
+        assert_eq!(chord("control + WIN + left"), chord("Ctrl+Super+Left"));
+        assert_eq!(
+            chord("Alt+Shift+F5"),
+            Ok(KeyChord {
+                mods: Modifiers::MOD1 | Modifiers::SHIFT,
+                keysym: 0xffc2,
+            })
+        );
+        assert_eq!(
+            chord("Return"),
+            Ok(KeyChord {
+                mods: Modifiers::empty(),
+                keysym: 0xff0d,
+            })
+        );
+    }
+
+    #[test]
+    fn keysyms() {
+        // letters are the lowercase keysym, whichever way they're written
+        assert_eq!(parse_keysym("a"), Some(0x61));
+        assert_eq!(parse_keysym("A"), Some(0x61));
+        assert_eq!(parse_keysym("7"), Some(0x37));
+        assert_eq!(parse_keysym("F1"), Some(0xffbe));
+        assert_eq!(parse_keysym("f35"), Some(0xffe0));
+        assert_eq!(parse_keysym("KP_0"), Some(0xffb0));
+        assert_eq!(parse_keysym("KP_Enter"), Some(0xff8d));
+        assert_eq!(parse_keysym("PageUp"), parse_keysym("Prior"));
+        assert_eq!(parse_keysym("0x1008ff13"), Some(0x1008ff13));
+
+        assert_eq!(parse_keysym("F0"), None);
+        assert_eq!(parse_keysym("F36"), None);
+        assert_eq!(parse_keysym("KP_10"), None);
+        assert_eq!(parse_keysym("0xzz"), None);
+        assert_eq!(parse_keysym("nope"), None);
+        assert_eq!(parse_keysym(""), None);
+    }
+
+    #[test]
+    fn bad_chords() {
+        assert_eq!(chord("Hyper+Left"), Err(KeyChordParseError));
+        assert_eq!(chord("Ctrl+Meta+Left"), Err(KeyChordParseError));
+        assert_eq!(chord("Ctrl+"), Err(KeyChordParseError));
+        assert_eq!(chord("Ctrl+Nope"), Err(KeyChordParseError));
+        assert_eq!(chord("Left+Ctrl"), Err(KeyChordParseError));
+        assert_eq!(chord(""), Err(KeyChordParseError));
+    }
+}
@@ -0,0 +1,127 @@
+use crate::geom::*;
+
+use std::str::FromStr;
+
+// LengthArg is a number of pixels, or a percentage of some length, as N%. a percentage can't be
+// more than the whole length either way
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum LengthArg {
+    Pixels(i16),
+    Percent(i32),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct LengthArgParseError;
+
+impl FromStr for LengthArg {
+    type Err = LengthArgParseError;
+    fn from_str(s: &str) -> Result<Self, LengthArgParseError> {
+        match s.strip_suffix('%') {
+            Some(pc) => pc
+                .parse::<i32>()
+                .ok()
+                .filter(|pc| (-100..=100).contains(pc))
+                .map(LengthArg::Percent)
+                .ok_or(LengthArgParseError),
+            None => s
+                .parse::<i16>()
+                .map(LengthArg::Pixels)
+                .map_err(|_| LengthArgParseError),
+        }
+    }
+}
+
+impl LengthArg {
+    pub(crate) fn of(self, len: i16) -> i16 {
+        match self {
+            LengthArg::Pixels(px) => px,
+            LengthArg::Percent(pc) => (len as i32 * pc)
+                .div_euclid(100)
+                .clamp(i16::MIN as i32, i16::MAX as i32)
+                as i16,
+        }
+    }
+}
+
+// NudgeArg is a distance to move a window, as dx,dy
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct NudgeArg {
+    pub dx: LengthArg,
+    pub dy: LengthArg,
+}
+
+impl FromStr for NudgeArg {
+    type Err = LengthArgParseError;
+    fn from_str(s: &str) -> Result<Self, LengthArgParseError> {
+        let (dx, dy) = s.split_once(',').ok_or(LengthArgParseError)?;
+        Ok(NudgeArg {
+            dx: dx.parse()?,
+            dy: dy.parse()?,
+        })
+    }
+}
+
+// MarginArg is the space to leave at the top, right, bottom and left. like CSS, one value is
+// used for all four, two are vertical and horizontal, and three are top, horizontal and bottom
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct MarginArg {
+    pub top: LengthArg,
+    pub right: LengthArg,
+    pub bottom: LengthArg,
+    pub left: LengthArg,
+}
+
+impl FromStr for MarginArg {
+    type Err = LengthArgParseError;
+    fn from_str(s: &str) -> Result<Self, LengthArgParseError> {
+        let v = s
+            .split(',')
+            .map(|l| l.parse::<LengthArg>())
+            .collect::<Result<Vec<_>, _>>()?;
+        let (top, right, bottom, left) = match v[..] {
+            [a] => (a, a, a, a),
+            [v, h] => (v, h, v, h),
+            [t, h, b] => (t, h, b, h),
+            [t, r, b, l] => (t, r, b, l),
+            _ => return Err(LengthArgParseError),
+        };
+        Ok(MarginArg {
+            top,
+            right,
+            bottom,
+            left,
+        })
+    }
+}
+
+impl MarginArg {
+    // offsets resolves the margin against an area. percentages are of the height for the top
+    // and bottom, and the width for the left and right
+    pub(crate) fn offsets(&self, area: &Box2D) -> SideOffsets2D {
+        SideOffsets2D::new(
+            self.top.of(area.height()),
+            self.right.of(area.width()),
+            self.bottom.of(area.height()),
+            self.left.of(area.width()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn length_args() {
+        let len = |s: &str| s.parse::<LengthArg>().map(|l| l.of(1920));
+        assert_eq!(len("10"), Ok(10));
+        assert_eq!(len("-20"), Ok(-20));
+        assert_eq!(len("1%"), Ok(19));
+        assert_eq!(len("100%"), Ok(1920));
+        assert_eq!(len("-10%"), Ok(-192));
+
+        for s in ["101%", "-101%", "99999999%", "99999", "%", "10px", ""] {
+            assert_eq!(len(s), Err(LengthArgParseError), "{:?}", s);
+        }
+    }
+}
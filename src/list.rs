@@ -0,0 +1,162 @@
+use crate::engine::{MonitorPolicyArgs, Screen};
+use crate::matcher::Matcher;
+use crate::session::{Session, Window, STICKY_DESKTOP};
+use crate::{capture_screen, ListArgs};
+
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use serde::Serialize;
+
+// ListedWindow is everything we know about a window, for mosaic list
+#[derive(Serialize, Debug)]
+struct ListedWindow {
+    id: u32,
+    parent: u32,
+    #[serde(rename = "type")]
+    typ: String,
+    selectable: bool,
+
+    // absolute geometry of the window itself, not including the frame
+    x: i16,
+    y: i16,
+    width: i16,
+    height: i16,
+
+    // frame extents, as top, right, bottom, left. negative for client-side decorations
+    frame: [i16; 4],
+
+    name: String,
+    instance: Option<String>,
+    class: Option<String>,
+
+    // None for a window on all desktops, or if the WM doesn't do desktops
+    desktop: Option<u32>,
+    sticky: bool,
+    monitor: Option<String>,
+}
+
+// list prints the windows, as a table or JSON. only the selectable ones, unless --all
+pub(crate) fn list(sess: &Session, args: &ListArgs) -> Result<()> {
+    let matcher = Matcher {
+        class: args.class.as_deref(),
+        instance: args.instance.as_deref(),
+        title: args.title.as_ref(),
+        pid: args.pid,
+        role: args.role.as_deref(),
+    };
+
+    let desktop = match args.current_desktop {
+        true => Some(
+            sess.current_desktop()
+                .context("couldn't get the current desktop")?,
+        ),
+        false => args.desktop,
+    };
+
+    let ids: Vec<_> = match args.all {
+        true => sess.windows().collect(),
+        false => sess.selectable().collect(),
+    };
+
+    let screen = capture_screen(sess)?;
+
+    let mut listed = vec![];
+    for id in ids {
+        let w = sess.window(id)?;
+
+        if desktop.is_some_and(|d| !w.on_desktop(d)) {
+            continue;
+        }
+        if !matcher.is_empty() {
+            match matcher.matches(&w) {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(e) => {
+                    warn!("couldn't check window {}: {}", id, e);
+                    continue;
+                }
+            }
+        }
+
+        let lw = match list_window(&screen, &w) {
+            Ok(lw) => lw,
+            Err(e) => {
+                warn!("couldn't get properties for window {}: {:#}", id, e);
+                continue;
+            }
+        };
+        if args
+            .monitor
+            .as_ref()
+            .is_some_and(|m| lw.monitor.as_ref() != Some(m))
+        {
+            continue;
+        }
+
+        debug!("listing window {}: {:?}", id, lw);
+        listed.push(lw);
+    }
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&listed)?);
+        return Ok(());
+    }
+
+    println!(
+        "{:<10} {:<10} {:<7} {:<3} {:<21} {:<15} {:<7} {:<10} {:<20} NAME",
+        "ID", "PARENT", "TYPE", "SEL", "GEOMETRY", "FRAME", "DESKTOP", "MONITOR", "CLASS"
+    );
+    for lw in listed {
+        let desktop = match (lw.sticky, lw.desktop) {
+            (true, _) => "all".to_string(),
+            (false, Some(d)) => d.to_string(),
+            (false, None) => "-".to_string(),
+        };
+        println!(
+            "{:<10} {:<10} {:<7} {:<3} {:<21} {:<15} {:<7} {:<10} {:<20} {}",
+            format!("{:#x}", lw.id),
+            format!("{:#x}", lw.parent),
+            lw.typ,
+            if lw.selectable { "y" } else { "n" },
+            format!("{}x{}{:+}{:+}", lw.width, lw.height, lw.x, lw.y),
+            lw.frame.map(|f| f.to_string()).join(","),
+            desktop,
+            lw.monitor.as_deref().unwrap_or("-"),
+            lw.class.as_deref().unwrap_or("-"),
+            lw.name,
+        );
+    }
+
+    Ok(())
+}
+
+fn list_window(screen: &Screen, w: &Window) -> Result<ListedWindow> {
+    let geom = w.abs_geom()?;
+    let frame = w
+        .frame_extents()
+        .context("failed to get window frame extents")?;
+    let (instance, class) = w.class()?.unzip();
+
+    // the monitor is decided the same way as for placement, by the outer geometry
+    let monitor = screen
+        .select_monitor(&geom.outer_box(frame), MonitorPolicyArgs::Overlap)
+        .map(|i| screen.monitors[i].name.clone());
+
+    Ok(ListedWindow {
+        id: w.id,
+        parent: w.parent,
+        typ: format!("{:?}", w.typ).to_lowercase(),
+        selectable: w.selectable,
+        x: geom.min.x,
+        y: geom.min.y,
+        width: geom.width(),
+        height: geom.height(),
+        frame: [frame.top, frame.right, frame.bottom, frame.left],
+        name: w.name()?,
+        instance,
+        class,
+        desktop: w.desktop.filter(|&d| d != STICKY_DESKTOP),
+        sticky: w.desktop == Some(STICKY_DESKTOP),
+        monitor,
+    })
+}
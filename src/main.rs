@@ -1,297 +1,1478 @@
+mod backend;
 mod condargs;
+mod config;
+mod daemon;
+mod engine;
+#[cfg(test)]
+mod fake;
 mod geom;
+mod grid;
+mod keys;
+mod length;
+mod list;
+mod matcher;
 mod session;
+mod size;
+mod snapshot;
+mod tile;
+mod x11;
 
-use crate::condargs::{ConditionArg, OrderedComparator};
+use crate::config::Config;
+use crate::engine::{
+    compute_new_geom, gap_box, gaps_and_margin, HintsPolicyArgs, HorizAlignArgs, Located,
+    MonitorArg, MonitorPolicyArgs, Options, Placed, Rect, Scene, SceneMonitor, SceneWindow, Screen,
+    VertAlignArgs,
+};
 use crate::geom::*;
-use crate::session::Session;
+use crate::grid::{cell_geom, GridCell, GridSize};
+use crate::length::{LengthArg, MarginArg, NudgeArg};
+use crate::matcher::{Matcher, MultipleArgs};
+use crate::session::{HistoryEntry, Session, Window, WindowState, STICKY_DESKTOP};
+use crate::size::{Size, SizeForRatioArg};
+use crate::tile::Layout;
 
 use anyhow::{Context, Result};
-use clap::{ArgGroup, Parser, ValueEnum};
-use log::debug;
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
+use log::{debug, warn};
+use regex::Regex;
+use serde::Serialize;
+use std::path::PathBuf;
 
 // XXX use ArgGroup enums: https://github.com/clap-rs/clap/issues/2621
 #[derive(Parser, Debug)]
-#[clap(group(ArgGroup::new("target").required(true)))]
+#[clap(group(
+    ArgGroup::new("target")
+        .required(true)
+        .multiple(true)
+        .args(["id", "active", "select", "monitor_windows", "class", "instance", "title", "pid", "role"])
+))]
+#[clap(group(
+    ArgGroup::new("matcher")
+        .multiple(true)
+        .args(["class", "instance", "title", "pid", "role"])
+))]
+#[clap(subcommand_negates_reqs = true, args_conflicts_with_subcommands = true)]
 struct RootArgs {
-    #[clap(long, group = "target", value_parser=clap_num::maybe_hex::<u32>)]
-    id: Option<u32>,
+    #[clap(subcommand)]
+    command: Option<Command>,
 
-    #[clap(long, group = "target")]
+    // can be given more than once, to place several windows
+    #[clap(long, value_parser=clap_num::maybe_hex::<u32>, conflicts_with_all=["active", "select", "monitor_windows", "matcher"])]
+    id: Vec<u32>,
+
+    #[clap(long, conflicts_with_all=["select", "monitor_windows", "matcher"])]
     active: bool,
 
-    #[clap(long, group = "target")]
+    #[clap(long, conflicts_with_all=["monitor_windows", "matcher"])]
     select: bool,
 
+    // every window on the monitor with the pointer
+    #[clap(long, conflicts_with = "matcher")]
+    monitor_windows: bool,
+
+    // the matchers can be combined; a window has to match all of them
+    #[clap(long)]
+    class: Option<String>,
+    #[clap(long)]
+    instance: Option<String>,
+    #[clap(long, value_parser=regex)]
+    title: Option<Regex>,
+    #[clap(long)]
+    pid: Option<u32>,
+    #[clap(long)]
+    role: Option<String>,
+
+    #[clap(long, value_enum, default_value_t = MultipleArgs::First)]
+    multiple: MultipleArgs,
+
+    // with several windows, --all places each one as if it was the only one (and is the same as
+    // --multiple=all), while --each lays them out side by side in the area
+    #[clap(long, conflicts_with_all=["multiple", "each"])]
+    all: bool,
+    #[clap(long, conflicts_with_all=["multiple", "undo"])]
+    each: bool,
+
+    #[clap(long)]
+    preset: Option<String>,
+
+    #[clap(long, conflicts_with_all=["preset", "monitor", "halign", "valign", "x", "y", "nudge", "width", "height", "gap", "margin", "grid", "cell", "span", "maximize", "desktop", "dry_run"])]
+    undo: bool,
+
+    // work out where the windows would go, and print it instead of moving them
+    #[clap(long, visible_alias = "print")]
+    dry_run: bool,
+    #[clap(long, value_enum, default_value_t = OutputFormatArgs::Text, requires = "dry_run")]
+    format: OutputFormatArgs,
+
+    // if the window ends up filling the available area across or down (or both), maximize it that
+    // way, so the WM knows
+    #[clap(long)]
+    maximize: bool,
+
+    // overlap if not given, here or in a preset
+    #[clap(long, value_enum)]
+    monitor_policy: Option<MonitorPolicyArgs>,
+
+    // what to do with the pixels left over when the window's size hints (eg a terminal's
+    // character cells) won't let it fill the space it was given. align if not given
+    #[clap(long, value_enum)]
+    hints_policy: Option<HintsPolicyArgs>,
+
+    #[clap(long)]
+    monitor: Option<MonitorArg>,
+
+    // send the window to another virtual desktop, by number (from 0), or next or prev
+    #[clap(long, value_parser=desktop)]
+    desktop: Option<DesktopArg>,
+
     #[clap(long)]
     halign: Option<HorizAlignArgs>,
 
     #[clap(long)]
     valign: Option<VertAlignArgs>,
 
-    #[clap(long, value_parser=percent_for_ratio, default_value=None)]
-    width: Option<Vec<PercentForRatioArg>>,
-    #[clap(long, value_parser=percent_for_ratio, default_value=None)]
-    height: Option<Vec<PercentForRatioArg>>,
+    // instead of aligning, put the window's edge this far into the available area. same units as
+    // --width and --height, so 10%, 200px, 1/4 or 100%-300px
+    #[clap(long, value_parser=size, conflicts_with="halign", allow_hyphen_values=true)]
+    x: Option<Size>,
+    #[clap(long, value_parser=size, conflicts_with="valign", allow_hyphen_values=true)]
+    y: Option<Size>,
+
+    // move the window by dx,dy (pixels, or N% of the area) after everything else, keeping it
+    // inside the available area
+    #[clap(long, value_parser=nudge, allow_hyphen_values=true)]
+    nudge: Option<NudgeArg>,
+
+    // sizes are N% (or just N), Npx, a fraction like 1/3, sums of those like 100%-40px, or keep
+    #[clap(long, value_parser=size_for_ratio, default_value=None)]
+    width: Option<Vec<SizeForRatioArg>>,
+    #[clap(long, value_parser=size_for_ratio, default_value=None)]
+    height: Option<Vec<SizeForRatioArg>>,
+
+    // space between windows, and around the edge of the available area. pixels, or N% of the area
+    #[clap(long, value_parser=length)]
+    gap: Option<LengthArg>,
+    #[clap(long, value_parser=margin)]
+    margin: Option<MarginArg>,
+
+    #[clap(long, value_parser=grid_size, conflicts_with_all=["halign", "valign", "x", "y", "width", "height"])]
+    grid: Option<GridSize>,
+    // --cell and --span need a --grid, but it could come from a preset, so check_args deals with
+    // that
+    #[clap(long, value_parser=grid_cell)]
+    cell: Option<GridCell>,
+    #[clap(long, value_parser=grid_size)]
+    span: Option<GridSize>,
+}
+
+impl From<&RootArgs> for Options {
+    fn from(args: &RootArgs) -> Options {
+        Options {
+            monitor_policy: args.monitor_policy.unwrap_or(MonitorPolicyArgs::Overlap),
+            hints_policy: args.hints_policy.unwrap_or(HintsPolicyArgs::Align),
+            maximize: args.maximize,
+            monitor: args.monitor.clone(),
+            halign: args.halign,
+            valign: args.valign,
+            x: args.x,
+            y: args.y,
+            nudge: args.nudge,
+            width: args.width.clone(),
+            height: args.height.clone(),
+            gap: args.gap,
+            margin: args.margin,
+            grid: args.grid,
+            cell: args.cell,
+            span: args.span,
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Stay running, and take commands from other mosaic processes and hotkeys
+    Daemon {
+        /// Config file to read hotkeys from [default: ~/.config/mosaic/config.toml]
+        #[clap(long)]
+        config: Option<PathBuf>,
+    },
+
+    /// Arrange all the windows on the current monitor
+    Tile(TileArgs),
+
+    /// Show the windows mosaic knows about
+    List(ListArgs),
+
+    /// Save or restore the positions of all windows
+    Snapshot {
+        #[clap(subcommand)]
+        command: SnapshotCommand,
+    },
+}
+
+#[derive(Args, Debug)]
+struct TileArgs {
+    #[clap(long, value_enum, default_value_t = Layout::MasterLeft)]
+    layout: Layout,
+
+    /// Size of the master window, in percent
+    #[clap(long, default_value_t = 50, value_parser = clap::value_parser!(i32).range(1..100))]
+    master: i32,
+
+    #[clap(long, value_parser=length)]
+    gap: Option<LengthArg>,
+    #[clap(long, value_parser=margin)]
+    margin: Option<MarginArg>,
 }
 
-type PercentForRatioArg = ConditionArg<i32, OrderedComparator<f32>>;
+#[derive(Args, Debug)]
+struct ListArgs {
+    /// Print JSON instead of a table
+    #[clap(long)]
+    json: bool,
+
+    /// Include every window, not just the ones mosaic can place (frames, docks, the root...)
+    #[clap(long)]
+    all: bool,
+
+    #[clap(long)]
+    class: Option<String>,
+    #[clap(long)]
+    instance: Option<String>,
+    #[clap(long, value_parser=regex)]
+    title: Option<Regex>,
+    #[clap(long)]
+    pid: Option<u32>,
+    #[clap(long)]
+    role: Option<String>,
+
+    /// Only windows on this desktop (or all desktops)
+    #[clap(long, conflicts_with = "current_desktop")]
+    desktop: Option<u32>,
+    /// Only windows on the current desktop (or all desktops)
+    #[clap(long)]
+    current_desktop: bool,
+
+    /// Only windows on the monitor with this name
+    #[clap(long)]
+    monitor: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum SnapshotCommand {
+    /// Save the positions of all windows
+    Save { name: String },
+    /// Move windows back to where they were when the snapshot was saved
+    Restore { name: String },
+}
+
+pub(crate) fn length(s: &str) -> Result<LengthArg, String> {
+    s.parse::<LengthArg>().map_err(|e| format!("{:?}", e))
+}
+
+pub(crate) fn margin(s: &str) -> Result<MarginArg, String> {
+    s.parse::<MarginArg>().map_err(|e| format!("{:?}", e))
+}
+
+pub(crate) fn desktop(s: &str) -> Result<DesktopArg, String> {
+    s.parse::<DesktopArg>().map_err(|e| format!("{:?}", e))
+}
+
+pub(crate) fn nudge(s: &str) -> Result<NudgeArg, String> {
+    s.parse::<NudgeArg>().map_err(|e| format!("{:?}", e))
+}
+
+pub(crate) fn size(s: &str) -> Result<Size, String> {
+    s.parse::<Size>().map_err(|e| format!("{:?}", e))
+}
+
+pub(crate) fn size_for_ratio(s: &str) -> Result<SizeForRatioArg, String> {
+    s.parse::<SizeForRatioArg>().map_err(|e| format!("{:?}", e))
+}
 
-pub(crate) fn percent_for_ratio(s: &str) -> Result<PercentForRatioArg, String> {
-    s.parse::<PercentForRatioArg>()
-        .map_err(|e| format!("{:?}", e))
+pub(crate) fn regex(s: &str) -> Result<Regex, String> {
+    Regex::new(s).map_err(|e| e.to_string())
+}
+
+pub(crate) fn grid_size(s: &str) -> Result<GridSize, String> {
+    s.parse::<GridSize>().map_err(|e| format!("{:?}", e))
+}
+
+pub(crate) fn grid_cell(s: &str) -> Result<GridCell, String> {
+    s.parse::<GridCell>().map_err(|e| format!("{:?}", e))
 }
 
 #[derive(Debug)]
 enum TargetArgs {
     None,
-    Id(u32),
+    Id(Vec<u32>),
     Select,
     Active,
+    MonitorWindows,
+    Match,
 }
 
-#[derive(ValueEnum, Clone, Copy, Debug)]
-enum HorizAlignArgs {
-    Left,
-    Middle,
-    Right,
+#[derive(Clone, Copy, Debug)]
+enum DesktopArg {
+    Next,
+    Prev,
+    Index(u32),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct DesktopArgParseError;
+
+impl std::str::FromStr for DesktopArg {
+    type Err = DesktopArgParseError;
+    fn from_str(s: &str) -> Result<Self, DesktopArgParseError> {
+        match s {
+            "next" => Ok(DesktopArg::Next),
+            "prev" => Ok(DesktopArg::Prev),
+            _ => s
+                .parse::<u32>()
+                .map(DesktopArg::Index)
+                .map_err(|_| DesktopArgParseError),
+        }
+    }
 }
+
 #[derive(ValueEnum, Clone, Copy, Debug)]
-enum VertAlignArgs {
-    Top,
-    Middle,
-    Bottom,
+enum OutputFormatArgs {
+    Text,
+    Json,
+    // everything the placement was worked out from, as JSON
+    Scene,
 }
 
 fn main() -> Result<()> {
-    let args = RootArgs::parse();
+    let mut args = RootArgs::parse();
+
+    env_logger::Builder::new().parse_default_env().init();
+
+    if let Some(Command::Daemon { ref config }) = args.command {
+        let config = Config::load(config.as_deref())?;
+        let sess = Session::init().context("failed to connect to X11 server")?;
+        return daemon::serve(&sess, &config);
+    }
+
+    if let Some(Command::Tile(ref tile_args)) = args.command {
+        let sess = Session::init().context("failed to connect to X11 server")?;
+        return tile::tile(&sess, &args, tile_args);
+    }
+
+    if let Some(Command::List(ref list_args)) = args.command {
+        let sess = Session::init().context("failed to connect to X11 server")?;
+        return list::list(&sess, list_args);
+    }
+
+    if let Some(Command::Snapshot { ref command }) = args.command {
+        let sess = Session::init().context("failed to connect to X11 server")?;
+        return match command {
+            SnapshotCommand::Save { name } => snapshot::save(&sess, name),
+            SnapshotCommand::Restore { name } => snapshot::restore(&sess, name),
+        };
+    }
+
+    // if there's a daemon running, it can do the work, and is much faster at it. it has its own
+    // config, so it takes care of presets and checking too. a dry run has to print here though,
+    // so it doesn't go to the daemon
+    if !args.dry_run && daemon::forward(std::env::args_os().skip(1))? {
+        return Ok(());
+    }
+
+    if args.preset.is_some() {
+        apply_preset(&mut args, &Config::load(None)?)?;
+    }
+    check_args(&args)?;
+
+    let sess = Session::init().context("failed to connect to X11 server")?;
+    run(&sess, &args)
+}
+
+// apply_preset fills in the options from the named preset. anything given on the command line
+// wins; that includes using a grid on the command line with a preset that aligns and sizes, or
+// vice versa, where the preset's conflicting options are ignored entirely
+fn apply_preset(args: &mut RootArgs, config: &Config) -> Result<()> {
+    let Some(ref name) = args.preset else {
+        return Ok(());
+    };
+    let preset = config
+        .presets
+        .get(name)
+        .with_context(|| format!("preset {:?} not found", name))?;
+    debug!("applying preset {:?}: {:?}", name, preset);
+
+    let cli_grid = args.grid.is_some() || args.cell.is_some() || args.span.is_some();
+    let cli_place = args.halign.is_some()
+        || args.valign.is_some()
+        || args.x.is_some()
+        || args.y.is_some()
+        || args.width.is_some()
+        || args.height.is_some();
+
+    if args.monitor.is_none() {
+        args.monitor = preset.monitor.clone();
+    }
+    args.desktop = args.desktop.or(preset.desktop);
+    args.maximize |= preset.maximize;
+    args.monitor_policy = args.monitor_policy.or(preset.monitor_policy);
+    args.hints_policy = args.hints_policy.or(preset.hints_policy);
+    args.gap = args.gap.or(preset.gap);
+    args.margin = args.margin.or(preset.margin);
+    args.nudge = args.nudge.or(preset.nudge);
+
+    if !cli_grid {
+        // a position on the command line beats the preset's alignment, and vice versa
+        if args.x.is_none() {
+            args.halign = args.halign.or(preset.halign);
+        }
+        if args.halign.is_none() {
+            args.x = args.x.or(preset.x);
+        }
+        if args.y.is_none() {
+            args.valign = args.valign.or(preset.valign);
+        }
+        if args.valign.is_none() {
+            args.y = args.y.or(preset.y);
+        }
+        args.width = args.width.take().or_else(|| preset.width.clone());
+        args.height = args.height.take().or_else(|| preset.height.clone());
+    }
+
+    if !cli_place {
+        args.grid = args.grid.or(preset.grid);
+        args.cell = args.cell.or(preset.cell);
+        args.span = args.span.or(preset.span);
+    }
+
+    Ok(())
+}
+
+// check_args does any validation that clap can't, before we go near the X server
+fn check_args(args: &RootArgs) -> Result<()> {
+    if args.grid.is_none() {
+        anyhow::ensure!(
+            args.cell.is_none() && args.span.is_none(),
+            "--cell and --span need --grid"
+        );
+    } else {
+        anyhow::ensure!(
+            args.halign.is_none()
+                && args.valign.is_none()
+                && args.x.is_none()
+                && args.y.is_none()
+                && args.width.is_none()
+                && args.height.is_none(),
+            "--grid can't be used with --halign, --valign, --x, --y, --width or --height"
+        );
+    }
+
+    if let Some(grid) = args.grid {
+        let cell = args.cell.unwrap_or(GridCell { col: 0, row: 0 });
+        let span = args.span.unwrap_or(GridSize { cols: 1, rows: 1 });
+        // in u32, so a huge cell can't overflow past the check
+        anyhow::ensure!(
+            cell.col as u32 + span.cols as u32 <= grid.cols as u32
+                && cell.row as u32 + span.rows as u32 <= grid.rows as u32,
+            "cell {},{} with span {}x{} doesn't fit in {}x{} grid",
+            cell.col,
+            cell.row,
+            span.cols,
+            span.rows,
+            grid.cols,
+            grid.rows
+        );
+    }
+    Ok(())
+}
+
+fn run(sess: &Session, args: &RootArgs) -> Result<()> {
+    let screen = capture_screen(sess)?;
+    debug!("screen: {:?}", screen);
+
+    let targets = find_targets(sess, &screen, args)?;
+    debug!("target window ids: {:?}", targets);
 
-    let target_arg = if let Some(id) = args.id {
-        TargetArgs::Id(id)
+    if args.each {
+        return place_each(sess, &screen, args, &targets);
+    }
+
+    // with several windows, one going wrong shouldn't stop the rest
+    if let [target_id] = targets[..] {
+        return place(sess, &screen, args, target_id);
+    }
+    let mut failed = 0;
+    for &target_id in &targets {
+        if let Err(e) = place(sess, &screen, args, target_id) {
+            warn!("failed to place window {}: {:#}", target_id, e);
+            failed += 1;
+        }
+    }
+    anyhow::ensure!(
+        failed == 0,
+        "failed to place {} of {} windows",
+        failed,
+        targets.len()
+    );
+    Ok(())
+}
+
+// find_targets works out which windows the command applies to
+fn find_targets(sess: &Session, screen: &Screen, args: &RootArgs) -> Result<Vec<u32>> {
+    let matcher = Matcher {
+        class: args.class.as_deref(),
+        instance: args.instance.as_deref(),
+        title: args.title.as_ref(),
+        pid: args.pid,
+        role: args.role.as_deref(),
+    };
+
+    let target_arg = if !args.id.is_empty() {
+        TargetArgs::Id(args.id.clone())
     } else if args.active {
         TargetArgs::Active
     } else if args.select {
         TargetArgs::Select
+    } else if args.monitor_windows {
+        TargetArgs::MonitorWindows
+    } else if !matcher.is_empty() {
+        TargetArgs::Match
     } else {
         TargetArgs::None
     };
 
-    env_logger::Builder::new().parse_default_env().init();
+    let windows = match target_arg {
+        TargetArgs::Id(ref ids) => ids
+            .iter()
+            .map(|&id| sess.window(id))
+            .collect::<Result<Vec<_>>>()?,
+        TargetArgs::Active => vec![sess
+            .active_window()
+            .context("failed to get active window")?],
+        TargetArgs::Select => vec![sess.select_window().context("failed to select window")?],
+        TargetArgs::MonitorWindows => {
+            let (_, found) = monitor_windows(sess, screen)?;
+            anyhow::ensure!(!found.is_empty(), "no windows on the current monitor");
+            return Ok(found);
+        }
+        TargetArgs::Match => {
+            let found = matcher.find(sess);
+            let multiple = match args.all || args.each {
+                true => MultipleArgs::All,
+                false => args.multiple,
+            };
+            return match (multiple, &found[..]) {
+                (_, []) => anyhow::bail!("no windows match {}", matcher),
+                (MultipleArgs::Error, [_, _, ..]) => {
+                    anyhow::bail!("{} windows match {}: {:?}", found.len(), matcher, found)
+                }
+                (MultipleArgs::All, _) => Ok(found),
+                (_, [first, ..]) => Ok(vec![*first]),
+            };
+        }
+        TargetArgs::None => unreachable!(),
+    };
 
-    let sess = Session::init().context("failed to connect to X11 server")?;
+    let mut targets = vec![];
+    for w in windows {
+        let id = resolve_selectable(sess, &w).with_context(|| {
+            format!(
+                "couldn't resolve target {:?} to a selectable window",
+                target_arg
+            )
+        })?;
+        // two ids could resolve to the same window, and it only gets placed once
+        if !targets.contains(&id) {
+            targets.push(id);
+        }
+    }
+    Ok(targets)
+}
 
-    let target_id = 'target: {
-        let w = match target_arg {
-            TargetArgs::Id(id) => sess.window(id),
-            TargetArgs::Active => sess
-                .active_window()
-                .context("failed to get active window")?,
-            TargetArgs::Select => sess.select_window().context("failed to select window")?,
-            TargetArgs::None => unreachable!(),
-        };
+// resolve_selectable finds the selectable window for the one asked for. we might have been given
+// a frame or some other WM decoration, or an inner window of the client, so look up and down the
+// tree for it. any we don't know about (because they went away, or the server wouldn't tell us)
+// are skipped
+fn resolve_selectable(sess: &Session, w: &Window) -> Option<u32> {
+    if w.selectable {
+        return Some(w.id);
+    }
 
-        if w.selectable {
-            break 'target w.id;
+    let root = sess.root().ok()?.id;
+    let mut parent = w.parent;
+    while parent > 0 && parent != root {
+        debug!(
+            "requested window {} not selectable, checking parent",
+            parent
+        );
+        let Some(pw) = sess.find_window(parent) else {
+            debug!("parent window {} not found", parent);
+            break;
+        };
+        if pw.selectable {
+            debug!("parent window {} selectable, using it", parent);
+            return Some(parent);
         }
+        parent = pw.parent;
+    }
 
-        let mut parent = w.parent;
-        while parent > 0 && parent != sess.root().id {
-            debug!(
-                "requested window {} not selectable, checking parent",
-                parent
-            );
-            let pw = sess.window(parent);
-            if pw.selectable {
-                debug!("parent window {} selectable, using it", parent);
-                break 'target parent;
+    if let Some(child) = w
+        .children
+        .iter()
+        .copied()
+        .find(|&cid| sess.find_window(cid).is_some_and(|cw| cw.selectable))
+    {
+        debug!("child window {} selectable, using it", child);
+        return Some(child);
+    }
+
+    None
+}
+
+// monitor_windows finds the selectable windows on the monitor with the pointer, which is the one
+// the user is looking at, on the current desktop. returns the monitor index too
+fn monitor_windows(sess: &Session, screen: &Screen) -> Result<(usize, Vec<u32>)> {
+    let pointer = screen.pointer.context("failed to get pointer position")?;
+    let monitor = screen
+        .monitor_at(pointer.into())
+        .context("pointer isn't on any monitor")?;
+    debug!("current monitor: {}", monitor);
+
+    let desktop = sess.current_desktop();
+    debug!("current desktop: {:?}", desktop);
+
+    let mut windows = vec![];
+    for id in sess.selectable() {
+        let w = sess.window(id)?;
+        if desktop.is_some_and(|d| !w.on_desktop(d)) {
+            continue;
+        }
+        let geom = match w.abs_geom() {
+            Ok(geom) => geom,
+            Err(e) => {
+                warn!("couldn't find where window {} is: {:#}", id, e);
+                continue;
             }
-            parent = pw.parent;
+        };
+        if screen.select_monitor(&geom, MonitorPolicyArgs::Overlap) == Some(monitor) {
+            windows.push(id);
         }
+    }
+    Ok((monitor, windows))
+}
 
-        if let Some(child) = w
-            .children
-            .iter()
-            .filter_map(|&cid| sess.window(cid).selectable.then_some(cid))
-            .next()
-        {
-            debug!("child window {} selectable, using it", child);
-            break 'target child;
-        }
+// place does the actual work on a single window
+fn place(sess: &Session, screen: &Screen, args: &RootArgs, target_id: u32) -> Result<()> {
+    if args.undo {
+        return undo(&sess.window(target_id)?);
+    }
 
-        anyhow::bail!(
-            "couldn't resolve target {:?} to a selectable window",
-            target_arg
-        );
+    let p = plan(sess, screen, args, target_id)?;
+    apply(&p, &engine::place(&p.window, &p.located, &args.into()))
+}
+
+// place_each lays the windows out side by side in the area computed for the first one. they all
+// go to the first window's monitor
+fn place_each(sess: &Session, screen: &Screen, args: &RootArgs, target_ids: &[u32]) -> Result<()> {
+    let plans = target_ids
+        .iter()
+        .map(|&id| plan(sess, screen, args, id))
+        .collect::<Result<Vec<_>>>()?;
+
+    let opts = Options::from(args);
+    let first = &plans[0].located;
+    let area = compute_new_geom(&first.current, &first.avail, &plans[0].window.hints, &opts);
+    debug!("computed area for {} windows: {:?}", plans.len(), area);
+
+    let (_, gaps) = gaps_and_margin(&first.avail, args.gap, args.margin);
+
+    // split along the long side, so the windows don't get too thin
+    let n = plans.len() as u16;
+    let grid = match area.width() >= area.height() {
+        true => GridSize { cols: n, rows: 1 },
+        false => GridSize { cols: 1, rows: n },
+    };
+    let span = GridSize { cols: 1, rows: 1 };
+
+    for (i, p) in plans.iter().enumerate() {
+        let i = i as u16;
+        let cell = match grid.cols {
+            1 => GridCell { col: 0, row: i },
+            _ => GridCell { col: i, row: 0 },
+        };
+        let geom = gap_box(&cell_geom(&area, grid, cell, span), &area, gaps);
+        debug!("window {} gets {:?}", p.target.id, geom);
+        apply(p, &engine::finish(&geom, &p.window, &first.avail, &opts))?;
+    }
+
+    Ok(())
+}
+
+// capture_screen collects everything about the screen the engine needs to place windows
+fn capture_screen(sess: &Session) -> Result<Screen> {
+    let pointer = match sess.pointer() {
+        Ok(pointer) => Some((pointer.x, pointer.y)),
+        Err(e) => {
+            debug!("couldn't get pointer position: {}", e);
+            None
+        }
     };
+    Ok(Screen {
+        root: (&sess.root()?.geom).into(),
+        monitors: sess
+            .monitors()
+            .map(|m| SceneMonitor {
+                name: m.name,
+                geom: (&m.geom).into(),
+            })
+            .collect(),
+        struts: sess
+            .struts()
+            .filter_map(|id| sess.window(id).ok()?.strut)
+            .collect(),
+        workarea: sess.workarea().map(|w| (&w).into()),
+        docks: sess
+            .docks()
+            .filter_map(|id| Some((&sess.window(id).ok()?.abs_geom().ok()?).into()))
+            .collect(),
+        pointer,
+    })
+}
+
+// Placement is everything we need to know to move a window
+struct Placement<'a> {
+    target: Window,
+    screen: &'a Screen,
+    window: SceneWindow,
+    // where the window is before we touch it, for the history
+    previous: HistoryEntry,
+    located: Located,
+    // the desktop to send the window to, if its changing
+    desktop: Option<u32>,
+    // if set, just print where the window would go
+    dry_run: Option<OutputFormatArgs>,
+}
 
+// plan finds out where the window is, and the area its going to be placed within
+fn plan<'a>(
+    sess: &Session,
+    screen: &'a Screen,
+    args: &RootArgs,
+    target_id: u32,
+) -> Result<Placement<'a>> {
     debug!("target window id: {}", target_id);
 
-    let target = sess.window(target_id);
+    let target = sess.window(target_id)?;
 
     let frame = target
         .frame_extents()
         .context("failed to get window frame extents")?;
     debug!("target frame extents: {:?}", frame);
 
-    let current_geom = {
-        let geom = target.abs_geom();
-        debug!("target geom: {:?}", geom);
+    let hints = target
+        .size_hints()
+        .context("failed to get window size hints")?;
+    debug!("target size hints: {:?}", hints);
+
+    let window = SceneWindow {
+        geom: (&target.abs_geom().context("failed to get window geometry")?).into(),
+        frame: [frame.top, frame.right, frame.bottom, frame.left],
+        hints,
+    };
+    debug!("target: {:?}", window);
 
-        let unframed = geom.outer_box(frame);
-        debug!("target unframed geom: {:?}", unframed);
+    let state = target.state().context("failed to get window state")?;
+    debug!("target state: {:?}", state);
 
-        unframed
+    let desktop = match args.desktop {
+        None => None,
+        Some(arg) => {
+            let desktop = resolve_desktop(sess, &target, arg)
+                .with_context(|| format!("couldn't find desktop {:?}", arg))?;
+            debug!("target desktop: {}", desktop);
+            Some(desktop)
+        }
     };
 
-    let avail_geom = sess
-        .desktops()
-        .filter_map(|&id| {
-            let w = sess.window(id);
-            let geom = w.abs_geom();
-            debug!("desktop {} box: {:?}", id, geom);
-            geom.intersects(&current_geom).then(|| {
-                debug!("target {} is on desktop {}", target_id, id);
-                geom
-            })
-        })
-        // XXX take the first one. better probably would be to overlap with the desktop, and take
-        // the one that has the largest overlap. or some other notion of "best" idk
-        .next()
-        .with_context(|| {
-            format!(
-                "couldn't determine which desktop contains window {}",
-                target_id
-            )
-        })?;
+    let previous = HistoryEntry {
+        geom: window.outer(),
+        state,
+        desktop: target.desktop,
+    };
 
-    debug!("desktop avail geom: {:?}", avail_geom);
+    let located = engine::locate(screen, &window, &args.into())
+        .with_context(|| format!("couldn't place window {}", target_id))?;
 
-    let avail_geom = sess.docks().fold(avail_geom, |avail, &id| {
-        let w = sess.window(id);
-        let geom = w.abs_geom();
-        debug!("dock {} box: {:?}", id, geom);
-        match avail.intersection(&geom) {
-            Some(overlap) if overlap == avail => {
-                debug!("dock {} covers avail area, ignoring it", id);
-                avail
-            }
+    Ok(Placement {
+        target,
+        screen,
+        window,
+        previous,
+        located,
+        desktop,
+        dry_run: args.dry_run.then_some(args.format),
+    })
+}
 
-            // dock doesn't intersect the area, ignore it
-            None => {
-                debug!("dock {} is outside avail area, ignoring it", id);
-                avail
-            }
+// apply moves the window to where its been placed, and remembers where it was
+fn apply(p: &Placement, placed: &Placed) -> Result<()> {
+    debug!(
+        "new client geom for window {}: {:?}",
+        p.target.id, placed.client
+    );
 
-            Some(overlap) => {
-                debug!(
-                    "dock {} overlaps avail, reducing (overlap {:?})",
-                    id, overlap
-                );
+    if let Some(format) = p.dry_run {
+        return print_placement(p, placed, format);
+    }
 
-                let mut regions = vec![];
-                if avail.min.x < overlap.min.x {
-                    // left of dock
-                    regions.push(Box2D::new(avail.min, (overlap.min.x, avail.max.y).into()));
-                }
-                if avail.max.x > overlap.max.x {
-                    // right of dock
-                    regions.push(Box2D::new((overlap.max.x, avail.min.y).into(), avail.max));
-                }
-                if avail.min.y < overlap.min.y {
-                    // above dock
-                    regions.push(Box2D::new(avail.min, (avail.max.x, overlap.min.y).into()));
-                }
-                if avail.max.y > overlap.max.y {
-                    // below dock
-                    regions.push(Box2D::new((avail.min.x, overlap.max.y).into(), avail.max));
-                }
+    // most WMs ignore a move for a maximized or fullscreen window, or put it straight back, so
+    // those states have to come off first. a shaded window would move, but stay rolled up
+    let clear = p.previous.state;
+    if !clear.is_empty() {
+        debug!("clearing state {:?} for window {}", clear, p.target.id);
+        p.target
+            .set_state(clear, false)
+            .context("failed to change window state")?;
+    }
+
+    p.target
+        .set_geom(&placed.client)
+        .context("failed to move/resize window")?;
 
-                // XXX guaranteed to have one. in some future nonsense, we'd select the "best" by
-                // some means. I don't do docks really though, so nothing for now
-                debug!("new avail regions (taking last): {:?}", regions);
-                regions.pop().unwrap()
+    if !placed.maximize.is_empty() {
+        debug!(
+            "setting state {:?} for window {}",
+            placed.maximize, p.target.id
+        );
+        p.target
+            .set_state(placed.maximize, true)
+            .context("failed to change window state")?;
+    }
+
+    if let Some(desktop) = p.desktop {
+        p.target
+            .set_desktop(desktop)
+            .context("failed to move window to desktop")?;
+    }
+
+    let mut history = p.target.history().context("failed to get window history")?;
+    history.push(p.previous);
+    p.target
+        .set_history(&history)
+        .context("failed to update window history")?;
+
+    Ok(())
+}
+
+// PrintedPlacement is what --dry-run prints for each window. before and after include the frame,
+// client is the window itself, which is what would actually be asked for
+#[derive(Serialize)]
+struct PrintedPlacement {
+    id: u32,
+    before: Rect,
+    after: Rect,
+    client: Rect,
+    // top, right, bottom, left
+    frame: [i16; 4],
+    avail: Rect,
+    maximize_horz: bool,
+    maximize_vert: bool,
+    desktop: Option<u32>,
+}
+
+// print_placement shows where the window would go, instead of moving it. JSON is one object per
+// line, so several windows can be read one at a time. a scene is everything the placement was
+// worked out from, for reproducing it without an X server
+fn print_placement(p: &Placement, placed: &Placed, format: OutputFormatArgs) -> Result<()> {
+    match format {
+        OutputFormatArgs::Text => {
+            let g = |b: &Box2D| format!("{}x{}{:+}{:+}", b.width(), b.height(), b.min.x, b.min.y);
+            let mut line = format!(
+                "{:#x}: {} -> {} (client {}, avail {})",
+                p.target.id,
+                g(&p.previous.geom),
+                g(&placed.outer),
+                g(&placed.client),
+                g(&p.located.avail)
+            );
+            if !placed.maximize.is_empty() {
+                line += &format!(", maximize {:?}", placed.maximize);
+            }
+            if let Some(desktop) = p.desktop {
+                line += &format!(", desktop {}", desktop);
             }
+            println!("{}", line);
+        }
+        OutputFormatArgs::Json => {
+            let printed = PrintedPlacement {
+                id: p.target.id,
+                before: (&p.previous.geom).into(),
+                after: (&placed.outer).into(),
+                client: (&placed.client).into(),
+                frame: p.window.frame,
+                avail: (&p.located.avail).into(),
+                maximize_horz: placed.maximize.contains(WindowState::MAXIMIZED_HORZ),
+                maximize_vert: placed.maximize.contains(WindowState::MAXIMIZED_VERT),
+                desktop: p.desktop,
+            };
+            println!("{}", serde_json::to_string(&printed)?);
         }
-    });
+        OutputFormatArgs::Scene => {
+            let scene = Scene {
+                screen: p.screen.clone(),
+                window: p.window.clone(),
+            };
+            println!("{}", serde_json::to_string_pretty(&scene)?);
+        }
+    }
+    Ok(())
+}
 
-    debug!("avail geom: {:?}", avail_geom);
+// undo puts the window back to the last entry in its history, and forgets it
+fn undo(target: &Window) -> Result<()> {
+    let frame = target
+        .frame_extents()
+        .context("failed to get window frame extents")?;
 
-    let new_geom = {
-        let geom = compute_new_geom(&current_geom, &avail_geom, &args);
-        debug!("computed new geom: {:?}", geom);
+    let mut history = target.history().context("failed to get window history")?;
+    let entry = history
+        .pop()
+        .with_context(|| format!("nothing to undo for window {}", target.id))?;
+    debug!("undoing to {:?}", entry);
 
-        let framed = geom.inner_box(frame);
-        debug!("computed new framed geom: {:?}", framed);
+    restore_entry(target, frame, &entry)?;
 
-        framed
-    };
+    target
+        .set_history(&history)
+        .context("failed to update window history")?;
+
+    Ok(())
+}
+
+// restore_entry puts the window at the entry's geometry (which includes the frame), in the entry's
+// state, on the entry's desktop
+fn restore_entry(target: &Window, frame: SideOffsets2D, entry: &HistoryEntry) -> Result<()> {
+    // the WM won't move a maximized or fullscreen window, so those have to come off first, and
+    // only go back on once its in place
+    let state = target.state().context("failed to get window state")?;
+    let remove = state - entry.state;
+    let add = entry.state - state;
+    debug!("state {:?}, removing {:?} adding {:?}", state, remove, add);
 
-    sess.window(target_id)
-        .set_geom(&new_geom)
+    if !remove.is_empty() {
+        target
+            .set_state(remove, false)
+            .context("failed to change window state")?;
+    }
+    target
+        .set_geom(&entry.geom.inner_box(frame))
         .context("failed to move/resize window")?;
+    if !add.is_empty() {
+        target
+            .set_state(add, true)
+            .context("failed to change window state")?;
+    }
+
+    if let Some(desktop) = entry.desktop.filter(|&d| target.desktop != Some(d)) {
+        target
+            .set_desktop(desktop)
+            .context("failed to move window to desktop")?;
+    }
 
     Ok(())
 }
 
-fn compute_new_geom(current: &Box2D, avail: &Box2D, args: &RootArgs) -> Box2D {
-    let ratio = avail.width() as f32 / avail.height() as f32;
+// resolve_desktop finds the desktop requested by --desktop. next and prev are relative to the
+// window's desktop, or the current one if its on all of them
+fn resolve_desktop(sess: &Session, target: &Window, arg: DesktopArg) -> Option<u32> {
+    let count = sess.desktop_count()?;
+    let current = match target.desktop {
+        Some(d) if d != STICKY_DESKTOP => d,
+        _ => sess.current_desktop()?,
+    };
+    match arg {
+        DesktopArg::Next => Some((current + 1) % count),
+        DesktopArg::Prev => Some((current + count - 1) % count),
+        DesktopArg::Index(i) => (i < count).then_some(i),
+    }
+}
 
-    debug!(
-        "ratio {} halign {:?} valign {:?} width {:?} height {:?}",
-        ratio, args.halign, args.valign, args.width, args.height
-    );
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake::{Fake, FakeWindow};
+    use crate::session::{Strut, StrutEdge};
+    use crate::size::SizeArg;
+    use serde::Deserialize;
+    use std::path::Path;
 
-    let w = args.width.as_ref().map_or_else(
-        || current.width(),
-        |v| {
-            v.iter().filter(|pc| pc.matches(ratio)).next().map_or_else(
-                || current.width(),
-                |pc| {
-                    debug!("selected width rule {:?} for ratio {}", pc, ratio);
-                    match pc.value {
-                        0 => 1,
-                        100 => avail.width(),
-                        w => (avail.width() as i32 * w).div_euclid(100) as i16,
-                    }
-                },
-            )
-        },
-    );
+    fn rect(x: i16, y: i16, w: i16, h: i16) -> Box2D {
+        Box2D::from_origin_and_size((x, y).into(), (w, h).into())
+    }
 
-    let h = args.height.as_ref().map_or_else(
-        || current.height(),
-        |v| {
-            v.iter().filter(|pc| pc.matches(ratio)).next().map_or_else(
-                || current.height(),
-                |pc| {
-                    debug!("selected height rule {:?} for ratio {}", pc, ratio);
-                    match pc.value {
-                        0 => 1,
-                        100 => avail.height(),
-                        h => (avail.height() as i32 * h).div_euclid(100) as i16,
-                    }
+    struct Windows {
+        term1: u32,
+        term2: u32,
+        browser: u32,
+        // on the left monitor, but on another desktop
+        hidden: u32,
+    }
+
+    // a 1080p monitor on the left of a 1440p one, which has a panel along the bottom. the pointer
+    // is on the left one, with two terminals, and there's a browser on the right
+    fn screen() -> (Fake, Windows) {
+        let mut fake = Fake::new(rect(0, 0, 4480, 1440));
+        fake.add_monitor("HDMI-1", rect(0, 0, 1920, 1080));
+        fake.add_monitor("DP-1", rect(1920, 0, 2560, 1440));
+        fake.set_desktops(0, 4);
+        fake.set_pointer((500, 500).into());
+
+        let none = StrutEdge {
+            size: 0,
+            start: 0,
+            end: 0,
+        };
+        fake.add_window(FakeWindow::dock(
+            rect(1920, 1400, 2560, 40),
+            Some(Strut {
+                left: none,
+                right: none,
+                top: none,
+                bottom: StrutEdge {
+                    size: 40,
+                    start: 1920,
+                    end: 4479,
                 },
+            }),
+        ));
+
+        let term = |geom| FakeWindow {
+            class: Some(("xterm".to_string(), "XTerm".to_string())),
+            ..FakeWindow::normal(geom)
+        };
+        let term1 = fake.add_window(term(rect(100, 100, 800, 600)));
+        let term2 = fake.add_window(term(rect(1000, 200, 640, 480)));
+        let browser = fake.add_window(FakeWindow {
+            class: Some(("Navigator".to_string(), "firefox".to_string())),
+            ..FakeWindow::normal(rect(2200, 100, 1200, 900))
+        });
+        let hidden = fake.add_window(FakeWindow {
+            desktop: Some(1),
+            ..FakeWindow::normal(rect(300, 300, 800, 600))
+        });
+
+        (
+            fake,
+            Windows {
+                term1,
+                term2,
+                browser,
+                hidden,
+            },
+        )
+    }
+
+    // mosaic runs a command line against the session, and catches up with the changes
+    fn mosaic(sess: &Session, args: &str) -> Result<()> {
+        let args =
+            RootArgs::try_parse_from(std::iter::once("mosaic").chain(args.split_whitespace()))?;
+        check_args(&args)?;
+        match args.command {
+            Some(Command::Tile(ref tile_args)) => tile::tile(sess, &args, tile_args)?,
+            _ => run(sess, &args)?,
+        }
+        sess.refresh()
+    }
+
+    fn geom(sess: &Session, id: u32) -> Box2D {
+        sess.window(id).unwrap().abs_geom().unwrap()
+    }
+
+    #[test]
+    fn place_by_id() {
+        let (fake, w) = screen();
+        let sess = Session::new(Box::new(fake));
+
+        let args = format!(
+            "--id {:#x} --halign left --valign top --width 50 --height 100",
+            w.term1
+        );
+        mosaic(&sess, &args).unwrap();
+        assert_eq!(geom(&sess, w.term1), rect(1, 20, 958, 1059));
+        assert_eq!(geom(&sess, w.term2), rect(1000, 200, 640, 480));
+
+        let history = sess.window(w.term1).unwrap().history().unwrap();
+        assert_eq!(
+            history,
+            vec![HistoryEntry {
+                geom: rect(99, 80, 802, 621),
+                state: WindowState::empty(),
+                desktop: Some(0),
+            }]
+        );
+
+        mosaic(&sess, &format!("--id {:#x} --undo", w.term1)).unwrap();
+        assert_eq!(geom(&sess, w.term1), rect(100, 100, 800, 600));
+        assert!(sess.window(w.term1).unwrap().history().unwrap().is_empty());
+    }
+
+    #[test]
+    fn unknown_id() {
+        let (fake, _) = screen();
+        let sess = Session::new(Box::new(fake));
+        assert!(mosaic(&sess, "--id 0x999 --halign left").is_err());
+    }
+
+    #[test]
+    fn grid_cell_out_of_range() {
+        let (fake, w) = screen();
+        let sess = Session::new(Box::new(fake));
+
+        for grid in ["--cell 2,0", "--cell 65535,0", "--cell 0,1 --span 1x65535"] {
+            let args = format!("--id {:#x} --grid 2x2 {}", w.term1, grid);
+            assert!(mosaic(&sess, &args).is_err(), "{}", grid);
+        }
+        assert_eq!(geom(&sess, w.term1), rect(100, 100, 800, 600));
+    }
+
+    #[test]
+    fn bad_lengths() {
+        assert!(RootArgs::try_parse_from(["mosaic", "--active", "--gap", "99999999%"]).is_err());
+        assert!(RootArgs::try_parse_from(["mosaic", "--active", "--nudge", "0,200%"]).is_err());
+    }
+
+    #[test]
+    fn negative_position() {
+        let args = RootArgs::try_parse_from(["mosaic", "--active", "--x", "-10%", "--y", "-20px"])
+            .unwrap();
+        assert_eq!(args.x, Some("-10%".parse().unwrap()));
+        assert_eq!(args.y, Some("-20px".parse().unwrap()));
+
+        let args =
+            RootArgs::try_parse_from(["mosaic", "--active", "--x=100%-300px", "--y", "-1/4"])
+                .unwrap();
+        assert_eq!(args.x, Some("100%-300px".parse().unwrap()));
+        assert_eq!(args.y, Some("-1/4".parse().unwrap()));
+    }
+
+    #[test]
+    fn active_window() {
+        let (mut fake, w) = screen();
+        fake.set_active(w.browser);
+        let sess = Session::new(Box::new(fake));
+
+        // the panel is on this monitor
+        mosaic(
+            &sess,
+            "--active --halign right --valign top --width 50 --height 100",
+        )
+        .unwrap();
+        assert_eq!(geom(&sess, w.browser), rect(3201, 20, 1278, 1379));
+        assert_eq!(geom(&sess, w.term1), rect(100, 100, 800, 600));
+    }
+
+    #[test]
+    fn no_active_window() {
+        // X says 0 whether _NET_ACTIVE_WINDOW is 0 or missing, and so does the fake
+        for active in [None, Some(0)] {
+            let (mut fake, w) = screen();
+            if let Some(id) = active {
+                fake.set_active(id);
+            }
+            let sess = Session::new(Box::new(fake));
+
+            let err = mosaic(&sess, "--active --halign left").unwrap_err();
+            assert!(
+                format!("{:#}", err).contains("no active window"),
+                "{:#}",
+                err
+            );
+            assert_eq!(geom(&sess, w.term1), rect(100, 100, 800, 600));
+        }
+    }
+
+    #[test]
+    fn selected_window() {
+        let (mut fake, w) = screen();
+        fake.set_selected(w.term2);
+        let sess = Session::new(Box::new(fake));
+
+        mosaic(&sess, "--select --monitor next").unwrap();
+        let monitor = sess.monitors().nth(1).unwrap();
+        assert!(monitor.geom.contains_box(&geom(&sess, w.term2)));
+    }
+
+    #[test]
+    fn matching() {
+        let (fake, w) = screen();
+        let sess = Session::new(Box::new(fake));
+
+        // the oldest one, unless asked for all of them
+        mosaic(&sess, "--class xterm --valign bottom").unwrap();
+        assert_eq!(geom(&sess, w.term1), rect(100, 479, 800, 600));
+        assert_eq!(geom(&sess, w.term2), rect(1000, 200, 640, 480));
+
+        mosaic(&sess, "--class XTERM --all --valign top").unwrap();
+        assert_eq!(geom(&sess, w.term1), rect(100, 20, 800, 600));
+        assert_eq!(geom(&sess, w.term2), rect(1000, 20, 640, 480));
+        assert_eq!(geom(&sess, w.browser), rect(2200, 100, 1200, 900));
+
+        assert!(mosaic(&sess, "--class nothing --valign top").is_err());
+    }
+
+    #[test]
+    fn maximized_window() {
+        let (fake, w) = screen();
+        let sess = Session::new(Box::new(fake));
+        let term1 = sess.window(w.term1).unwrap();
+        term1
+            .set_state(
+                WindowState::MAXIMIZED_HORZ | WindowState::MAXIMIZED_VERT,
+                true,
             )
-        },
-    );
+            .unwrap();
 
-    let x = match args.halign {
-        Some(p) => match p {
-            HorizAlignArgs::Left => avail.min.x,
-            HorizAlignArgs::Middle => avail.min.x + (avail.width() - w).div_euclid(2),
-            HorizAlignArgs::Right => avail.max.x - w,
-        },
-        None => current.min.x,
-    };
+        // the maximized state has to come off for the move, and is remembered for undo
+        mosaic(
+            &sess,
+            &format!("--id {:#x} --halign left --width 50", w.term1),
+        )
+        .unwrap();
+        assert_eq!(term1.state().unwrap(), WindowState::empty());
+        assert_eq!(
+            term1.history().unwrap()[0].state,
+            WindowState::MAXIMIZED_HORZ | WindowState::MAXIMIZED_VERT
+        );
 
-    let y = match args.valign {
-        Some(p) => match p {
-            VertAlignArgs::Top => avail.min.y,
-            VertAlignArgs::Middle => avail.min.y + (avail.height() - h).div_euclid(2),
-            VertAlignArgs::Bottom => avail.max.y - h,
-        },
-        None => current.min.y,
-    };
+        let args = format!(
+            "--id {:#x} --halign left --valign top --width 50 --height 100 --maximize",
+            w.term1
+        );
+        mosaic(&sess, &args).unwrap();
+        assert_eq!(term1.state().unwrap(), WindowState::MAXIMIZED_VERT);
+    }
+
+    #[test]
+    fn desktop() {
+        let (fake, w) = screen();
+        let sess = Session::new(Box::new(fake));
+
+        mosaic(&sess, &format!("--id {:#x} --desktop next", w.term1)).unwrap();
+        assert_eq!(sess.window(w.term1).unwrap().desktop, Some(1));
+        mosaic(&sess, &format!("--id {:#x} --desktop prev", w.hidden)).unwrap();
+        assert_eq!(sess.window(w.hidden).unwrap().desktop, Some(0));
+
+        // undo brings it back to the desktop it was on, as well as where it was
+        mosaic(
+            &sess,
+            &format!("--id {:#x} --desktop next --halign left", w.term1),
+        )
+        .unwrap();
+        assert_eq!(sess.window(w.term1).unwrap().desktop, Some(2));
+        mosaic(&sess, &format!("--id {:#x} --undo", w.term1)).unwrap();
+        assert_eq!(sess.window(w.term1).unwrap().desktop, Some(1));
+        mosaic(&sess, &format!("--id {:#x} --undo", w.term1)).unwrap();
+        assert_eq!(sess.window(w.term1).unwrap().desktop, Some(0));
+        assert_eq!(geom(&sess, w.term1), rect(100, 100, 800, 600));
+    }
+
+    #[test]
+    fn dry_run() {
+        let (fake, w) = screen();
+        let sess = Session::new(Box::new(fake));
+
+        let args = format!("--id {:#x} --halign left --width 50 --dry-run", w.term1);
+        mosaic(&sess, &args).unwrap();
+        assert_eq!(geom(&sess, w.term1), rect(100, 100, 800, 600));
+        assert!(sess.window(w.term1).unwrap().history().unwrap().is_empty());
+    }
+
+    #[test]
+    fn monitor_windows_each() {
+        let (fake, w) = screen();
+        let sess = Session::new(Box::new(fake));
+
+        mosaic(
+            &sess,
+            "--monitor-windows --each --halign left --valign top --width 100 --height 100",
+        )
+        .unwrap();
+        assert_eq!(geom(&sess, w.term1), rect(1, 20, 958, 1059));
+        assert_eq!(geom(&sess, w.term2), rect(961, 20, 958, 1059));
+        assert_eq!(geom(&sess, w.hidden), rect(300, 300, 800, 600));
+        assert_eq!(geom(&sess, w.browser), rect(2200, 100, 1200, 900));
+    }
+
+    // with_preset parses a command line, and applies the presets from the config
+    fn with_preset(config: &str, args: &str) -> Result<RootArgs> {
+        let config: Config = toml::from_str(config)?;
+        let mut args =
+            RootArgs::try_parse_from(std::iter::once("mosaic").chain(args.split_whitespace()))?;
+        apply_preset(&mut args, &config)?;
+        check_args(&args)?;
+        Ok(args)
+    }
+
+    const PRESETS: &str = r#"
+        [presets.left]
+        halign = "left"
+        valign = "top"
+        width = 50
+        gap = 10
+
+        [presets.cell]
+        grid = "2x1"
+        cell = "1,0"
+        margin = 8
+        maximize = true
+        monitor_policy = "pointer"
+        hints_policy = "center"
+        desktop = "next"
+    "#;
+
+    fn width_is(args: &RootArgs, s: &str) -> bool {
+        let want = size(s).unwrap();
+        args.width.as_ref().is_some_and(
+            |w| matches!(w[..], [ref w] if matches!(w.value, SizeArg::Fixed(s) if s == want)),
+        )
+    }
+
+    #[test]
+    fn preset_precedence() {
+        // the preset fills in whatever isn't on the command line
+        let args = with_preset(PRESETS, "--id 1 --preset left --valign bottom").unwrap();
+        assert!(matches!(args.halign, Some(HorizAlignArgs::Left)));
+        assert!(matches!(args.valign, Some(VertAlignArgs::Bottom)));
+        assert!(matches!(args.gap, Some(LengthArg::Pixels(10))));
+        assert!(width_is(&args, "50"));
+
+        let args = with_preset(PRESETS, "--id 1 --preset left --width 30 --gap 2").unwrap();
+        assert!(width_is(&args, "30"));
+        assert!(matches!(args.gap, Some(LengthArg::Pixels(2))));
+
+        // a position beats the preset's alignment, on that axis only
+        let args = with_preset(PRESETS, "--id 1 --preset left --x 10%").unwrap();
+        assert!(args.halign.is_none());
+        assert_eq!(args.x, Some(size("10%").unwrap()));
+        assert!(matches!(args.valign, Some(VertAlignArgs::Top)));
+
+        // a grid on the command line drops the preset's alignment and size, but not the rest
+        let args = with_preset(PRESETS, "--id 1 --preset left --grid 3x3").unwrap();
+        assert!(args.halign.is_none() && args.valign.is_none() && args.width.is_none());
+        assert_eq!(args.grid, Some(GridSize { cols: 3, rows: 3 }));
+        assert!(matches!(args.gap, Some(LengthArg::Pixels(10))));
+
+        // and placing on the command line drops the preset's grid
+        let args = with_preset(PRESETS, "--id 1 --preset cell --halign right").unwrap();
+        assert!(args.grid.is_none() && args.cell.is_none());
+        assert!(matches!(args.halign, Some(HorizAlignArgs::Right)));
+        assert!(args.margin.is_some());
+
+        let args = with_preset(PRESETS, "--id 1 --preset cell --cell 0,0").unwrap();
+        assert_eq!(args.grid, Some(GridSize { cols: 2, rows: 1 }));
+        assert_eq!(args.cell, Some(GridCell { col: 0, row: 0 }));
+        assert!(args.maximize);
+        assert_eq!(args.monitor_policy, Some(MonitorPolicyArgs::Pointer));
+        assert_eq!(args.hints_policy, Some(HintsPolicyArgs::Center));
+        assert!(matches!(args.desktop, Some(DesktopArg::Next)));
+
+        // the policies and the desktop on the command line beat the preset's
+        let args = with_preset(
+            PRESETS,
+            "--id 1 --preset cell --monitor-policy center --hints-policy expand --desktop 0",
+        )
+        .unwrap();
+        assert_eq!(args.monitor_policy, Some(MonitorPolicyArgs::Center));
+        assert_eq!(args.hints_policy, Some(HintsPolicyArgs::Expand));
+        assert!(matches!(args.desktop, Some(DesktopArg::Index(0))));
+
+        // and without either, the policies are the defaults
+        let opts = Options::from(&with_preset(PRESETS, "--id 1 --preset left").unwrap());
+        assert_eq!(opts.monitor_policy, MonitorPolicyArgs::Overlap);
+        assert_eq!(opts.hints_policy, HintsPolicyArgs::Align);
+        assert!(!opts.maximize);
+
+        assert!(with_preset(PRESETS, "--id 1 --preset nope").is_err());
+    }
+
+    #[test]
+    fn tile() {
+        let (fake, w) = screen();
+        let sess = Session::new(Box::new(fake));
+
+        mosaic(&sess, "tile --layout rows").unwrap();
+        assert_eq!(geom(&sess, w.term1), rect(1, 20, 1918, 519));
+        assert_eq!(geom(&sess, w.term2), rect(1, 560, 1918, 519));
+        assert_eq!(geom(&sess, w.hidden), rect(300, 300, 800, 600));
+    }
+
+    // a golden scene is a scene and some command lines to place its window with, each with the
+    // client geometry it should end up at. run with MOSAIC_BLESS=1 to write what the engine does
+    // now back into the files, then check the diff
+    #[derive(Serialize, Deserialize)]
+    struct Golden {
+        description: String,
+        #[serde(flatten)]
+        scene: Scene,
+        cases: Vec<Case>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Case {
+        args: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        expect: Option<Rect>,
+    }
+
+    fn run_case(scene: &Scene, args: &str) -> Result<Rect> {
+        let argv = ["mosaic", "--id", "1"]
+            .into_iter()
+            .chain(args.split_whitespace());
+        let args = RootArgs::try_parse_from(argv)?;
+        check_args(&args)?;
+        let opts = Options::from(&args);
+        let located = engine::locate(&scene.screen, &scene.window, &opts)?;
+        Ok((&engine::place(&scene.window, &located, &opts).client).into())
+    }
+
+    #[test]
+    fn golden_scenes() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/scenes");
+        let bless = std::env::var_os("MOSAIC_BLESS").is_some();
+
+        let mut paths: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|p| p.extension().is_some_and(|e| e == "json"))
+            .collect();
+        paths.sort();
+        assert!(!paths.is_empty(), "no scenes in {}", dir.display());
+
+        let mut failures = vec![];
+        for path in paths {
+            let name = path.file_name().unwrap().to_string_lossy().to_string();
+            let mut golden: Golden = serde_json::from_str(&std::fs::read_to_string(&path).unwrap())
+                .unwrap_or_else(|e| panic!("{}: {}", name, e));
+
+            for case in &mut golden.cases {
+                let got = run_case(&golden.scene, &case.args)
+                    .unwrap_or_else(|e| panic!("{}: {}: {:#}", name, case.args, e));
+                if bless {
+                    case.expect = Some(got);
+                } else if case.expect != Some(got) {
+                    failures.push(format!(
+                        "{}: {}: expected {:?}, got {:?}",
+                        name, case.args, case.expect, got
+                    ));
+                }
+            }
+
+            if bless {
+                let text = serde_json::to_string_pretty(&golden).unwrap();
+                std::fs::write(&path, text + "\n").unwrap();
+            }
+        }
 
-    Box2D::from_origin_and_size((x, y).into(), (w, h).into())
+        assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+    }
 }
@@ -0,0 +1,112 @@
+use crate::session::{Session, Window};
+
+use anyhow::Result;
+use clap::ValueEnum;
+use log::{debug, warn};
+use regex::Regex;
+
+// Matcher picks out windows by their properties. every condition given has to match. class,
+// instance and role are compared ignoring case, because nobody remembers whether its "firefox" or
+// "Firefox"
+#[derive(Debug, Default)]
+pub(crate) struct Matcher<'a> {
+    pub class: Option<&'a str>,
+    pub instance: Option<&'a str>,
+    pub title: Option<&'a Regex>,
+    pub pid: Option<u32>,
+    pub role: Option<&'a str>,
+}
+
+// what to do when a matcher finds more than one window
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub(crate) enum MultipleArgs {
+    // the one with the lowest id, which is usually the oldest
+    First,
+    All,
+    Error,
+}
+
+impl std::fmt::Display for Matcher<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut conds = vec![];
+        if let Some(class) = self.class {
+            conds.push(format!("class {:?}", class));
+        }
+        if let Some(instance) = self.instance {
+            conds.push(format!("instance {:?}", instance));
+        }
+        if let Some(title) = self.title {
+            conds.push(format!("title /{}/", title));
+        }
+        if let Some(pid) = self.pid {
+            conds.push(format!("pid {}", pid));
+        }
+        if let Some(role) = self.role {
+            conds.push(format!("role {:?}", role));
+        }
+        write!(f, "{}", conds.join(", "))
+    }
+}
+
+impl Matcher<'_> {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.class.is_none()
+            && self.instance.is_none()
+            && self.title.is_none()
+            && self.pid.is_none()
+            && self.role.is_none()
+    }
+
+    pub(crate) fn matches(&self, w: &Window) -> Result<bool> {
+        // only fetch what we need, cheapest first
+        if let Some(pid) = self.pid
+            && w.pid()? != Some(pid)
+        {
+            return Ok(false);
+        }
+
+        if self.class.is_some() || self.instance.is_some() {
+            let Some((instance, class)) = w.class()? else {
+                return Ok(false);
+            };
+            if self.class.is_some_and(|c| !c.eq_ignore_ascii_case(&class))
+                || self
+                    .instance
+                    .is_some_and(|i| !i.eq_ignore_ascii_case(&instance))
+            {
+                return Ok(false);
+            }
+        }
+
+        if let Some(role) = self.role
+            && !w.role()?.is_some_and(|r| r.eq_ignore_ascii_case(role))
+        {
+            return Ok(false);
+        }
+
+        if let Some(title) = self.title
+            && !title.is_match(&w.name()?)
+        {
+            return Ok(false);
+        }
+
+        Ok(true)
+    }
+
+    // find returns the ids of the selectable windows that match, in id order. a window we can't
+    // check (usually because it went away while we were looking) is skipped
+    pub(crate) fn find(&self, sess: &Session) -> Vec<u32> {
+        let mut found = vec![];
+        for id in sess.selectable() {
+            match sess.window(id).and_then(|w| self.matches(&w)) {
+                Ok(true) => {
+                    debug!("window {} matches {}", id, self);
+                    found.push(id);
+                }
+                Ok(false) => {}
+                Err(e) => warn!("couldn't check window {}: {:#}", id, e),
+            }
+        }
+        found
+    }
+}
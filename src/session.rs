@@ -1,98 +1,86 @@
+use crate::backend::{Backend, Event, WindowInfo};
 use crate::geom::*;
+use crate::keys::{Keycode, Modifiers};
+use crate::x11::X11;
 
+use anyhow::{Context, Result};
 use log::{debug, warn};
-use std::cell::OnceCell;
+use serde::{Deserialize, Serialize};
+use std::cell::{Ref, RefCell};
 use std::collections::{BTreeMap, BTreeSet};
-use std::rc::Rc;
-use xcb::{x, Xid};
-
-xcb::atoms_struct! {
-    #[derive(Copy, Clone, Debug)]
-    struct Atoms {
-        wm_state => b"WM_STATE",
-
-        net_wm_window_type => b"_NET_WM_WINDOW_TYPE",
-        net_wm_window_type_normal => b"_NET_WM_WINDOW_TYPE_NORMAL",
-        net_wm_window_type_dock => b"_NET_WM_WINDOW_TYPE_DOCK",
-        net_wm_window_type_desktop => b"_NET_WM_WINDOW_TYPE_DESKTOP",
-
-        net_active_window => b"_NET_ACTIVE_WINDOW",
-
-        net_frame_extents => b"_NET_FRAME_EXTENTS",
-        gtk_frame_extents => b"_GTK_FRAME_EXTENTS",
-
-        net_moveresize_window => b"_NET_MOVERESIZE_WINDOW",
-
-        net_wm_name => b"_NET_WM_NAME",
-    }
-}
+use std::os::fd::BorrowedFd;
+use std::rc::{Rc, Weak};
 
 bitflags::bitflags! {
-    struct MoveResizeWindowFlags: u32 {
-        const GRAVITY_IMPLIED    = 0;
-        const GRAVITY_NORTH_WEST = 1;
-        const GRAVITY_NORTH      = 2;
-        const GRAVITY_NORTH_EAST = 3;
-        const GRAVITY_WEST       = 4;
-        const GRAVITY_CENTER     = 5;
-        const GRAVITY_EAST       = 6;
-        const GRAVITY_SOUTH_WEST = 7;
-        const GRAVITY_SOUTH      = 8;
-        const GRAVITY_SOUTH_EAST = 9;
-        const GRAVITY_STATIC     = 10;
-        const X                  = 1 << 8;
-        const Y                  = 1 << 9;
-        const WIDTH              = 1 << 10;
-        const HEIGHT             = 1 << 11;
+    // WindowState is the parts of _NET_WM_STATE that affect where a window is
+    #[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+    pub struct WindowState: u32 {
+        const MAXIMIZED_VERT = 1 << 0;
+        const MAXIMIZED_HORZ = 1 << 1;
+        const FULLSCREEN     = 1 << 2;
+        const SHADED         = 1 << 3;
     }
 }
 
-// max property length in 4-byte ints. 2K is overkill but enough for any property we want
-const PROPERTY_LONG_LENGTH: u32 = 512;
+// _NET_WM_DESKTOP for a window that's on all desktops
+pub const STICKY_DESKTOP: u32 = 0xFFFFFFFF;
 
-// X cursor font glyph index for window selection
-const XC_CROSSHAIR: u16 = 34;
-const XC_CROSSHAIR_MASK: u16 = 35;
+// how many placements we remember for each window
+const HISTORY_LEN: usize = 16;
+
+// HistoryEntry is where a window was before we moved it: its geometry including the frame, its
+// state, and the desktop it was on (None if the WM doesn't do desktops)
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct HistoryEntry {
+    pub geom: Box2D,
+    pub state: WindowState,
+    pub desktop: Option<u32>,
+}
 
 // Session is sort of the entire X11 session at a moment in time. Not _exactly_ because the
 // connection is live, but sort of conceptually what you expect.
 //
 // really, its a refcounted wrapper over SessionImpl, a sort of "internal" top object, so we can
-// hand out references to it to let eg window ops feel natural
+// hand out references to it to let eg window ops feel natural. the talking to the server is done
+// by the backend
 pub struct Session(Rc<SessionImpl>);
 
 struct SessionImpl {
-    conn: xcb::Connection,
-    atoms: Atoms,
-    root: x::Window,
-    wg: OnceCell<WindowGroup>,
+    backend: Box<dyn Backend>,
+    wg: RefCell<Option<WindowGroup>>,
+    monitors: RefCell<Option<Vec<Monitor>>>,
+    key_presses: RefCell<Vec<KeyPress>>,
 }
 
 // WindowGroup is the snapshot of all the windows and any interesting categories or relationships.
 // its conceptually part of Session/SessionImpl, but held separately so it can be lazily
-// constructed and (in the future) refreshed
+// constructed, and then kept up to date from events if the session is watching
 #[derive(Debug, Default)]
 struct WindowGroup {
     windows: BTreeMap<u32, Window>,
     desktop: BTreeSet<u32>,
     dock: BTreeSet<u32>,
+    strut: BTreeSet<u32>,
 }
 
-// Window represents a wraps a single X11 window. It has a reference to the session it came from so
+// Window represents a wraps a single window. It has a reference to the session it came from so
 // that it can call back into it for more advanced calls that require additional data from the
-// server (eg extents) or state from other windows (eg absolute position)
-#[derive(Debug)]
+// server (eg extents) or state from other windows (eg absolute position). The reference is weak,
+// because the session holds all the windows, and we don't want a cycle.
+#[derive(Clone, Debug)]
 pub struct Window {
-    sess: Session,
-    xw: x::Window,
+    sess: Weak<SessionImpl>,
     pub id: u32,
     pub parent: u32,
     pub children: Vec<u32>,
     pub geom: Box2D,
     pub typ: WindowType,
     pub selectable: bool,
+    pub strut: Option<Strut>,
+    // the virtual desktop its on, or STICKY_DESKTOP. None if the WM doesn't do desktops
+    pub desktop: Option<u32>,
 }
-#[derive(PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum WindowType {
     Normal,
     Dock,
@@ -100,262 +88,441 @@ pub enum WindowType {
     Root,
 }
 
+// Monitor is a physical output (or several that RandR treats as one), in root window space
+#[derive(Clone, PartialEq, Debug)]
+pub struct Monitor {
+    pub name: String,
+    pub geom: Box2D,
+}
+
+// KeyPress is a press of a grabbed key, with the modifiers that were held (less the ignored ones)
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct KeyPress {
+    pub keycode: Keycode,
+    pub mods: Modifiers,
+}
+
+// Strut is the space a window (usually a panel) reserves along the edges of the screen. Note that
+// its the edges of the whole X screen, not of any particular monitor.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Strut {
+    pub left: StrutEdge,
+    pub right: StrutEdge,
+    pub top: StrutEdge,
+    pub bottom: StrutEdge,
+}
+
+// StrutEdge is the thickness reserved from one screen edge, and the (inclusive) range along that
+// edge that it covers
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct StrutEdge {
+    pub size: i16,
+    pub start: i16,
+    pub end: i16,
+}
+
+// SizeHints is the sizes a client is willing to be, from WM_NORMAL_HINTS. these are for the client
+// window itself, not including any frame
+#[derive(Clone, Copy, PartialEq, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SizeHints {
+    pub min: Option<(i16, i16)>,
+    pub max: Option<(i16, i16)>,
+    pub base: Option<(i16, i16)>,
+    pub inc: Option<(i16, i16)>,
+    // min and max aspect ratios, as width/height
+    pub aspect: Option<(f32, f32)>,
+}
+
 impl Session {
-    pub(crate) fn init() -> xcb::Result<Session> {
-        let (conn, scr_num) = xcb::Connection::connect(None)?;
+    pub(crate) fn init() -> Result<Session> {
+        Ok(Session::new(Box::new(X11::connect()?)))
+    }
 
-        let atoms = Atoms::intern_all(&conn)?;
+    pub(crate) fn new(backend: Box<dyn Backend>) -> Session {
+        Session(Rc::new(SessionImpl {
+            backend,
+            wg: RefCell::new(None),
+            monitors: RefCell::new(None),
+            key_presses: RefCell::new(vec![]),
+        }))
+    }
 
-        let root = conn
-            .get_setup()
-            .roots()
-            .nth(scr_num as usize)
-            .unwrap()
-            .to_owned()
-            .root();
+    // window is an error if we don't know the window, which could be because its gone away, or
+    // the server wouldn't tell us about it
+    pub(crate) fn window(&self, id: u32) -> Result<Window> {
+        self.find_window(id)
+            .with_context(|| format!("window {:#x} not found", id))
+    }
+    pub(crate) fn find_window(&self, id: u32) -> Option<Window> {
+        self.window_group().windows.get(&id).cloned()
+    }
+    pub(crate) fn root(&self) -> Result<Window> {
+        self.window(self.0.backend.root())
+    }
 
-        Ok(Session(Rc::new(SessionImpl {
-            conn,
-            atoms,
-            root,
-            wg: OnceCell::new(),
-        })))
+    pub(crate) fn windows(&self) -> impl Iterator<Item = u32> {
+        let windows: Vec<_> = self.window_group().windows.keys().copied().collect();
+        windows.into_iter()
+    }
+    pub(crate) fn desktops(&self) -> impl Iterator<Item = u32> {
+        let desktops: Vec<_> = self.window_group().desktop.iter().copied().collect();
+        desktops.into_iter()
+    }
+    pub(crate) fn docks(&self) -> impl Iterator<Item = u32> {
+        let docks: Vec<_> = self.window_group().dock.iter().copied().collect();
+        docks.into_iter()
+    }
+    pub(crate) fn selectable(&self) -> impl Iterator<Item = u32> {
+        let selectable: Vec<_> = self
+            .window_group()
+            .windows
+            .values()
+            .filter_map(|w| w.selectable.then_some(w.id))
+            .collect();
+        selectable.into_iter()
     }
 
-    pub(crate) fn window(&self, id: u32) -> &Window {
-        &self.window_group().windows[&id]
+    // monitors are the physical outputs, as boxes in root window space, ordered left to right
+    // (then top to bottom) so they can be sensibly stepped through. the backend (ie RandR) is the
+    // source of truth; if it doesn't know we fall back to desktop windows, and then to the root
+    // window itself, which is the best we can do
+    pub(crate) fn monitors(&self) -> impl Iterator<Item = Monitor> {
+        if self.0.monitors.borrow().is_none() {
+            let mut monitors = self.find_monitors();
+            monitors.sort_by_key(|m| (m.geom.min.x, m.geom.min.y));
+            *self.0.monitors.borrow_mut() = Some(monitors);
+        }
+        let monitors = self.0.monitors.borrow().clone().unwrap_or_default();
+        monitors.into_iter()
     }
-    pub(crate) fn root(&self) -> &Window {
-        self.window(self.0.root.resource_id())
+
+    fn find_monitors(&self) -> Vec<Monitor> {
+        match self.0.backend.monitors() {
+            Ok(monitors) if !monitors.is_empty() => return monitors,
+            Ok(_) => {}
+            Err(e) => warn!("monitor query failed: {:#}", e),
+        }
+
+        let desktops: Vec<_> = self
+            .desktops()
+            .filter_map(|id| {
+                Some(Monitor {
+                    name: format!("desktop-{:#x}", id),
+                    geom: self.window(id).and_then(|w| w.abs_geom()).ok()?,
+                })
+            })
+            .collect();
+        if !desktops.is_empty() {
+            debug!("using desktop windows as monitors: {:?}", desktops);
+            return desktops;
+        }
+
+        let root = match self.root() {
+            Ok(root) => Monitor {
+                name: "root".to_string(),
+                geom: root.geom,
+            },
+            Err(e) => {
+                warn!("no monitors at all: {:#}", e);
+                return vec![];
+            }
+        };
+        debug!("using root window as monitor: {:?}", root);
+        vec![root]
     }
 
-    pub(crate) fn desktops(&self) -> impl Iterator<Item = &u32> {
-        self.window_group().desktop.iter()
+    pub(crate) fn struts(&self) -> impl Iterator<Item = u32> {
+        let struts: Vec<_> = self.window_group().strut.iter().copied().collect();
+        struts.into_iter()
     }
-    pub(crate) fn docks(&self) -> impl Iterator<Item = &u32> {
-        self.window_group().dock.iter()
+
+    pub(crate) fn current_desktop(&self) -> Option<u32> {
+        self.0.backend.current_desktop()
+    }
+
+    pub(crate) fn desktop_count(&self) -> Option<u32> {
+        self.0.backend.desktop_count()
+    }
+
+    pub(crate) fn workarea(&self) -> Option<Box2D> {
+        self.0.backend.workarea()
     }
 
-    fn window_group(&self) -> &WindowGroup {
-        self.0.wg.get_or_init(|| {
-            let mut wg = WindowGroup::default();
+    fn window_group(&self) -> Ref<'_, WindowGroup> {
+        if self.0.wg.borrow().is_none() {
+            let wg = self.load_window_group();
+            *self.0.wg.borrow_mut() = Some(wg);
+        }
+        Ref::map(self.0.wg.borrow(), |wg| wg.as_ref().unwrap())
+    }
+
+    fn load_window_group(&self) -> WindowGroup {
+        let mut wg = WindowGroup::default();
+        for info in self.0.backend.load_windows() {
+            wg.insert(self.make_window(info));
+        }
+        wg
+    }
 
-            struct WindowCookies {
-                xw: x::Window,
-                parent: u32,
-                geom: x::GetGeometryCookie,
-                state_prop: x::GetPropertyCookie,
-                type_prop: x::GetPropertyCookie,
+    fn make_window(&self, info: WindowInfo) -> Window {
+        Window {
+            sess: Rc::downgrade(&self.0),
+            id: info.id,
+            parent: info.parent,
+            children: info.children,
+            geom: info.geom,
+            typ: info.typ,
+            selectable: info.selectable,
+            strut: info.strut,
+            desktop: info.desktop,
+        }
+    }
+
+    // reload_window fetches everything about a window again, and replaces it in the group. its
+    // place in the tree doesn't change
+    fn reload_window(&self, id: u32) {
+        let Some((parent, children)) = self.0.wg.borrow().as_ref().and_then(|wg| {
+            let w = wg.windows.get(&id)?;
+            Some((w.parent, w.children.clone()))
+        }) else {
+            return;
+        };
+
+        let w = self
+            .0
+            .backend
+            .load_window(id, parent)
+            .map(|info| self.make_window(WindowInfo { children, ..info }));
+
+        if let Some(wg) = self.0.wg.borrow_mut().as_mut() {
+            match w {
+                Some(w) => wg.insert(w),
+                None => wg.remove(id),
             }
+        }
+    }
 
-            fn get_window_state(
-                sess: &Session,
-                xw: x::Window,
-                parent: u32,
-            ) -> Vec<(WindowCookies, Vec<u32>)> {
-                let tree_cookie = sess.x_query_tree(xw);
-
-                let cookies = WindowCookies {
-                    xw,
-                    parent,
-                    geom: sess.x_get_geometry(xw),
-                    state_prop: sess.x_get_property(xw, sess.0.atoms.wm_state, x::ATOM_ANY),
-                    type_prop: sess.x_get_property(
-                        xw,
-                        sess.0.atoms.net_wm_window_type,
-                        x::ATOM_ANY,
-                    ),
-                };
-
-                match sess.0.conn.wait_for_reply(tree_cookie) {
-                    Ok(tree) => {
-                        let parent = xw.resource_id();
-                        let children = tree
-                            .children()
-                            .iter()
-                            .map(|&cxw| cxw.resource_id())
-                            .collect();
-
-                        std::iter::once((cookies, children))
-                            .chain(
-                                tree.children()
-                                    .iter()
-                                    .map(|&cxw| get_window_state(sess, cxw, parent))
-                                    .into_iter()
-                                    .flatten(),
-                            )
-                            .collect()
-                    }
-                    Err(e) => {
-                        warn!("QueryTree for window {:?} failed: {}", xw, e);
-                        vec![(cookies, vec![])]
+    // invalidate throws away everything we know about the windows and monitors, so it will be
+    // reloaded from the server next time its needed
+    pub(crate) fn invalidate(&self) {
+        debug!("invalidating window group and monitors");
+        self.0.wg.replace(None);
+        self.0.monitors.replace(None);
+    }
+
+    // watch asks the server to tell us about changes to the window tree and the outputs, so a
+    // long-running session can keep up. call refresh() to bring everything up to date.
+    pub(crate) fn watch(&self) -> Result<()> {
+        self.0.backend.watch()?;
+
+        // reload everything, which will ask for events on every window as it goes
+        self.invalidate();
+        self.window_group();
+
+        Ok(())
+    }
+
+    // event_fd is readable when there might be events for refresh() to pick up. None if they
+    // never turn up by themselves, so there's nothing to wait for
+    pub(crate) fn event_fd(&self) -> Option<BorrowedFd<'_>> {
+        self.0.backend.event_fd()
+    }
+
+    // refresh deals with everything the server has sent us since last time, without blocking,
+    // updating the window group to match. handling an event can mean asking the server about a
+    // window, and more events can come in while we wait for the answer, so we keep going until
+    // there's none left. otherwise they'd sit in the backend's queue where poll can't see them
+    pub(crate) fn refresh(&self) -> Result<()> {
+        loop {
+            let events = self.0.backend.poll_events()?;
+            if events.is_empty() {
+                return Ok(());
+            }
+            for ev in events {
+                self.handle_event(ev);
+            }
+        }
+    }
+
+    fn handle_event(&self, ev: Event) {
+        if let Event::KeyPress(press) = ev {
+            self.0.key_presses.borrow_mut().push(press);
+            return;
+        }
+
+        // if we don't have a window group yet, there's nothing to update; it'll be current when
+        // we load it
+        if self.0.wg.borrow().is_none() {
+            if let Event::MonitorsChanged = ev {
+                self.0.monitors.replace(None);
+            }
+            return;
+        }
+
+        match ev {
+            Event::MonitorsChanged => {
+                self.0.monitors.replace(None);
+            }
+
+            Event::Created { id, parent } => {
+                if let Some(info) = self.0.backend.load_window(id, parent) {
+                    let w = self.make_window(info);
+                    if let Some(wg) = self.0.wg.borrow_mut().as_mut() {
+                        wg.attach(w.id, parent);
+                        wg.insert(w);
                     }
                 }
             }
 
-            for (wc, children) in get_window_state(self, self.0.root, self.0.root.resource_id()) {
-                let geom = self.0.conn.wait_for_reply(wc.geom);
-                let state_prop = self.0.conn.wait_for_reply(wc.state_prop);
-                let type_prop = self.0.conn.wait_for_reply(wc.type_prop);
-                match (geom, state_prop, type_prop) {
-                    (Err(e), _, _) => warn!("GetGeometry for window {:?} failed: {}", wc.xw, e),
-                    (_, Err(e), _) => {
-                        warn!("GetProperty(WM_STATE) for window {:?} failed: {}", wc.xw, e)
-                    }
-                    (_, _, Err(e)) => warn!(
-                        "GetProperty(NET_WM_WINDOW_TYPE) for window {:?} failed: {}",
-                        wc.xw, e
-                    ),
-                    (Ok(geom), Ok(state_prop), Ok(type_prop)) => {
-                        let id = wc.xw.resource_id();
-
-                        let typ = match wc.xw == self.0.root {
-                            true => WindowType::Root,
-                            false => match type_prop.length() {
-                                // some clients (Spotify) do not set a _NET_WM_WINDOW_TYPE at all.
-                                // we already. we just treat them as TYPE_NORMAL here, because
-                                // unless they've been selected somehow it won't even matter.
-                                0 => WindowType::Normal,
-                                _ => match type_prop.value::<x::Atom>()[0] {
-                                    v if v == self.0.atoms.net_wm_window_type_dock => {
-                                        WindowType::Dock
-                                    }
-                                    v if v == self.0.atoms.net_wm_window_type_desktop => {
-                                        WindowType::Desktop
-                                    }
-                                    _ => WindowType::Normal,
-                                },
-                            },
-                        };
-
-                        // ICCCM mandates client root windows have WM_STATE, and we are only
-                        // interested in NormalState (1) and in _NET_WM_WINDOW_TYPE_NORMAL
-                        let selectable = state_prop.r#type() == self.0.atoms.wm_state
-                            && state_prop.value::<u32>()[0] == 1
-                            && typ == WindowType::Normal;
-
-                        let w = Window {
-                            sess: Session(self.0.clone()),
-                            id,
-                            parent: wc.parent,
-                            children,
-                            xw: wc.xw,
-                            geom: Box2D::from_origin_and_size(
-                                (geom.x(), geom.y()).into(),
-                                (geom.width() as i16, geom.height() as i16).into(),
-                            ),
-                            typ,
-                            selectable,
-                        };
-
-                        match w.typ {
-                            WindowType::Dock => {
-                                wg.dock.insert(id);
-                                ()
-                            }
-                            WindowType::Desktop => {
-                                wg.desktop.insert(id);
-                                ()
-                            }
-                            _ => {}
-                        };
-
-                        wg.windows.insert(id, w);
-                    }
+            Event::Destroyed(id) => {
+                if let Some(wg) = self.0.wg.borrow_mut().as_mut() {
+                    wg.detach(id);
+                    wg.remove(id);
                 }
             }
 
-            wg
-        })
-    }
-
-    pub(crate) fn active_window(&self) -> xcb::Result<&Window> {
-        let active_prop = self.0.conn.wait_for_reply(self.x_get_property(
-            self.0.root,
-            self.0.atoms.net_active_window,
-            x::ATOM_WINDOW,
-        ))?;
-        let id = active_prop.value()[0];
-        Ok(self.window(id))
-    }
-
-    pub(crate) fn select_window(&self) -> xcb::Result<&Window> {
-        let font = self.0.conn.generate_id();
-        self.0.conn.send_request(&x::OpenFont {
-            fid: font,
-            name: b"cursor",
-        });
-
-        let cursor = self.0.conn.generate_id();
-        self.0.conn.send_request(&x::CreateGlyphCursor {
-            cid: cursor,
-            source_font: font,
-            mask_font: font,
-            source_char: XC_CROSSHAIR,
-            mask_char: XC_CROSSHAIR_MASK,
-            fore_red: 0x0000,
-            fore_green: 0x0000,
-            fore_blue: 0x0000,
-            back_red: 0xffff,
-            back_green: 0xffff,
-            back_blue: 0xffff,
-        });
-
-        self.0
-            .conn
-            .wait_for_reply(self.0.conn.send_request(&x::GrabPointer {
-                owner_events: false,
-                grab_window: self.0.root,
-                event_mask: x::EventMask::BUTTON_PRESS | x::EventMask::BUTTON_RELEASE,
-                pointer_mode: x::GrabMode::Sync,
-                keyboard_mode: x::GrabMode::Async,
-                confine_to: self.0.root,
-                cursor: cursor,
-                time: x::CURRENT_TIME,
-            }))?;
-
-        let selected = loop {
-            self.0.conn.send_request(&x::AllowEvents {
-                mode: x::Allow::SyncPointer,
-                time: x::CURRENT_TIME,
-            });
-            self.0.conn.flush()?;
-
-            if let xcb::Event::X(x::Event::ButtonPress(ev)) = self.0.conn.wait_for_event()? {
-                let w = ev.child();
-                if !w.is_none() {
-                    break w;
+            Event::Configured { id, geom } => {
+                if let Some(w) = self
+                    .0
+                    .wg
+                    .borrow_mut()
+                    .as_mut()
+                    .and_then(|wg| wg.windows.get_mut(&id))
+                {
+                    w.geom = geom;
                 }
             }
-        };
 
-        self.0.conn.send_request(&x::UngrabPointer {
-            time: x::CURRENT_TIME,
-        });
-        self.0.conn.flush()?;
+            Event::Reparented { id, parent, pos } => {
+                if let Some(wg) = self.0.wg.borrow_mut().as_mut() {
+                    wg.detach(id);
+                    wg.attach(id, parent);
+                    if let Some(w) = wg.windows.get_mut(&id) {
+                        w.parent = parent;
+                        w.geom = Box2D::from_origin_and_size(pos, w.geom.size());
+                    }
+                }
+            }
+
+            Event::Changed(id) => self.reload_window(id),
+
+            Event::KeyPress(_) => {}
+        }
+    }
+
+    pub(crate) fn active_window(&self) -> Result<Window> {
+        match self.0.backend.active_window()? {
+            0 => anyhow::bail!("no active window"),
+            id => self.window(id),
+        }
+    }
+
+    pub(crate) fn select_window(&self) -> Result<Window> {
+        self.window(self.0.backend.select_window()?)
+    }
+
+    // grab_key asks the server to send us presses of a key with exactly the given modifiers held,
+    // no matter which window has focus. returns the keycodes that were grabbed
+    pub(crate) fn grab_key(&self, mods: Modifiers, keysym: u32) -> Result<Vec<Keycode>> {
+        self.0.backend.grab_key(mods, keysym)
+    }
 
-        Ok(self.window(selected.resource_id()))
+    // take_key_presses returns the grabbed key presses that have arrived since last time
+    pub(crate) fn take_key_presses(&self) -> Vec<KeyPress> {
+        self.0.key_presses.take()
     }
 
-    fn x_query_tree(&self, xw: x::Window) -> x::QueryTreeCookie {
-        self.0.conn.send_request(&x::QueryTree { window: xw })
+    pub(crate) fn pointer(&self) -> Result<Point2D> {
+        self.0.backend.pointer()
+    }
+}
+
+impl WindowGroup {
+    // insert adds a window, or replaces one we already had
+    fn insert(&mut self, w: Window) {
+        self.remove(w.id);
+        if w.strut.is_some() {
+            self.strut.insert(w.id);
+        }
+        match w.typ {
+            WindowType::Dock => {
+                self.dock.insert(w.id);
+            }
+            WindowType::Desktop => {
+                self.desktop.insert(w.id);
+            }
+            _ => {}
+        };
+        self.windows.insert(w.id, w);
     }
 
-    fn x_get_geometry(&self, xw: x::Window) -> x::GetGeometryCookie {
-        self.0.conn.send_request(&x::GetGeometry {
-            drawable: x::Drawable::Window(xw),
-        })
+    fn remove(&mut self, id: u32) {
+        self.windows.remove(&id);
+        self.desktop.remove(&id);
+        self.dock.remove(&id);
+        self.strut.remove(&id);
     }
 
-    fn x_get_property(&self, xw: x::Window, prop: x::Atom, ty: x::Atom) -> x::GetPropertyCookie {
-        self.0.conn.send_request(&x::GetProperty {
-            window: xw,
-            delete: false,
-            property: prop,
-            r#type: ty,
-            long_offset: 0,
-            long_length: PROPERTY_LONG_LENGTH,
-        })
+    // attach adds a window to its parent's list of children
+    fn attach(&mut self, id: u32, parent: u32) {
+        if let Some(pw) = self.windows.get_mut(&parent)
+            && !pw.children.contains(&id)
+        {
+            pw.children.push(id);
+        }
+    }
+
+    // detach removes a window from its parent's list of children
+    fn detach(&mut self, id: u32) {
+        let Some(parent) = self.windows.get(&id).map(|w| w.parent) else {
+            return;
+        };
+        if let Some(pw) = self.windows.get_mut(&parent) {
+            pw.children.retain(|&cid| cid != id);
+        }
+    }
+}
+
+impl SizeHints {
+    // fit finds the largest size no bigger than the one given that the client will accept, the
+    // way ICCCM says a WM should. it can only come out bigger if that's less than the min size
+    pub(crate) fn fit(&self, (mut w, mut h): (i16, i16)) -> (i16, i16) {
+        // each of base and min stands in for the other if its missing
+        let base = self.base.or(self.min).unwrap_or((0, 0));
+        let min = self.min.or(self.base).unwrap_or((1, 1));
+
+        // a zero max means no max on that side
+        if let Some((max_w, max_h)) = self.max {
+            if max_w > 0 {
+                w = w.min(max_w);
+            }
+            if max_h > 0 {
+                h = h.min(max_h);
+            }
+        }
+
+        // aspect is measured without the base size, but not the min size. shrink whichever side
+        // is too long
+        if let Some((min_aspect, max_aspect)) = self.aspect {
+            let base = self.base.unwrap_or((0, 0));
+            let (bw, bh) = (w - base.0, h - base.1);
+            if bw > 0 && bh > 0 {
+                let aspect = bw as f32 / bh as f32;
+                if aspect > max_aspect {
+                    w = base.0 + (bh as f32 * max_aspect) as i16;
+                } else if aspect < min_aspect {
+                    h = base.1 + (bw as f32 / min_aspect) as i16;
+                }
+            }
+        }
+
+        if let Some((inc_w, inc_h)) = self.inc {
+            w = base.0 + (w - base.0).div_euclid(inc_w) * inc_w;
+            h = base.1 + (h - base.1).div_euclid(inc_h) * inc_h;
+        }
+
+        (w.max(min.0).max(1), h.max(min.1).max(1))
     }
 }
 
@@ -366,109 +533,96 @@ impl std::fmt::Debug for Session {
 }
 
 impl Window {
-    pub(crate) fn abs_geom(&self) -> Box2D {
+    fn sess(&self) -> Session {
+        Session(self.sess.upgrade().expect("window outlived its session"))
+    }
+
+    // abs_geom is the window's geometry in root window space. its an error if we don't know one
+    // of its parents, because then we can't know where it is
+    pub(crate) fn abs_geom(&self) -> Result<Box2D> {
+        let sess = self.sess();
+        let root = sess.0.backend.root();
         let mut parent = self.parent;
         let mut geom = self.geom;
-        while parent > 0 && parent != self.sess.root().id {
-            let pw = self.sess.window(parent);
+        while parent > 0 && parent != root {
+            let pw = sess.window(parent)?;
             geom = geom.translate(pw.geom.min.to_vector());
             parent = pw.parent;
         }
-        geom
-    }
-
-    pub(crate) fn frame_extents(&self) -> xcb::Result<SideOffsets2D> {
-        // batch request for net_extents, because most windows won't have gtk_extents and we want
-        // to limit the wait as much as possible
-        let net_extents_prop_cookie = self.sess.x_get_property(
-            self.xw,
-            self.sess.0.atoms.net_frame_extents,
-            x::ATOM_CARDINAL,
-        );
-
-        // gtk apps with client-side decorations. _GTK_FRAME_EXTENTS is the border and shadow
-        // region of the client window. we prefer this over _NET_FRAME_EXTENTS, and because its
-        // "inside" the window, we negate the offsets
-        let gtk_extents_prop = self.sess.0.conn.wait_for_reply(self.sess.x_get_property(
-            self.xw,
-            self.sess.0.atoms.gtk_frame_extents,
-            x::ATOM_CARDINAL,
-        ))?;
-
-        if gtk_extents_prop.r#type() == x::ATOM_CARDINAL {
-            let v: &[u32] = gtk_extents_prop.value();
-            debug!("window {} using gtk frame extents: {:?}", self.id, v);
-            // Cardinal order: left, right, top, bottom
-            // SideOffsets2D order: top, right, bottom, left
-            return Ok(-SideOffsets2D::new(
-                v[2] as i16,
-                v[1] as i16,
-                v[3] as i16,
-                v[0] as i16,
-            ));
-        }
+        Ok(geom)
+    }
 
-        // fall back to standard _NET_FRAME_EXTENTS
-        let net_extents_prop = self.sess.0.conn.wait_for_reply(net_extents_prop_cookie)?;
-        match net_extents_prop.r#type() {
-            x::ATOM_CARDINAL => {
-                let v: &[u32] = net_extents_prop.value();
-                debug!("window {} using net frame extents: {:?}", self.id, v);
-                // Cardinal order: left, right, top, bottom
-                // SideOffsets2D order: top, right, bottom, left
-                Ok(SideOffsets2D::new(
-                    v[2] as i16,
-                    v[1] as i16,
-                    v[3] as i16,
-                    v[0] as i16,
-                ))
-            }
-            _ => {
-                debug!("window {} has no frame extents, assuming zero", self.id);
-                Ok(SideOffsets2D::zero())
-            }
+    // frame_extents is the size of the WM's frame around the window. for clients that draw their
+    // own decorations, its negative: the shadow is part of the window
+    pub(crate) fn frame_extents(&self) -> Result<SideOffsets2D> {
+        self.sess().0.backend.frame_extents(self.id)
+    }
+
+    // on_desktop is true if the window can be seen on the given desktop. a window without a
+    // desktop is on all of them, because the WM doesn't do desktops, or hasn't said
+    pub(crate) fn on_desktop(&self, desktop: u32) -> bool {
+        match self.desktop {
+            None | Some(STICKY_DESKTOP) => true,
+            Some(d) => d == desktop,
         }
     }
 
-    pub(crate) fn _name(&self) -> xcb::Result<String> {
-        // XXX some lazy cache for properties would be better
-        let name_prop = self.sess.0.conn.wait_for_reply(self.sess.x_get_property(
-            self.xw,
-            self.sess.0.atoms.net_wm_name,
-            x::ATOM_ANY,
-        ))?;
-        Ok(String::from_utf8_lossy(name_prop.value()).to_string())
-    }
-
-    pub(crate) fn set_geom(&self, geom: &Box2D) -> xcb::Result<()> {
-        let ev = x::ClientMessageEvent::new(
-            self.xw,
-            self.sess.0.atoms.net_moveresize_window,
-            x::ClientMessageData::Data32([
-                (MoveResizeWindowFlags::X
-                    | MoveResizeWindowFlags::Y
-                    | MoveResizeWindowFlags::WIDTH
-                    | MoveResizeWindowFlags::HEIGHT
-                    | MoveResizeWindowFlags::GRAVITY_STATIC)
-                    .bits(),
-                geom.min.x as u32,
-                geom.min.y as u32,
-                geom.width() as u32,
-                geom.height() as u32,
-            ]),
-        );
-
-        self.sess.0.conn.send_request(&x::SendEvent {
-            propagate: false,
-            destination: x::SendEventDest::Window(self.sess.root().xw),
-            event_mask: x::EventMask::SUBSTRUCTURE_REDIRECT | x::EventMask::SUBSTRUCTURE_NOTIFY,
-            event: &ev,
-        });
-
-        self.sess.0.conn.flush()?;
-
-        // XXX window geom no longer matches actual geom, schedule refresh somehow
+    // set_desktop asks the WM to move the window to another desktop
+    pub(crate) fn set_desktop(&self, desktop: u32) -> Result<()> {
+        self.sess().0.backend.set_desktop(self.id, desktop)
+    }
 
-        Ok(())
+    pub(crate) fn state(&self) -> Result<WindowState> {
+        self.sess().0.backend.state(self.id)
+    }
+
+    // set_state asks the WM to turn the given states on or off. the rest are left alone
+    pub(crate) fn set_state(&self, state: WindowState, on: bool) -> Result<()> {
+        self.sess().0.backend.set_state(self.id, state, on)
+    }
+
+    // history is the placements we've made for this window, oldest first. the backend keeps it
+    // with the window (on X, in a property), so it lives exactly as long as the window does, and
+    // any mosaic process can see it
+    pub(crate) fn history(&self) -> Result<Vec<HistoryEntry>> {
+        self.sess().0.backend.history(self.id)
+    }
+
+    pub(crate) fn set_history(&self, history: &[HistoryEntry]) -> Result<()> {
+        // only the newest entries
+        let history = &history[history.len().saturating_sub(HISTORY_LEN)..];
+        self.sess().0.backend.set_history(self.id, history)
+    }
+
+    pub(crate) fn name(&self) -> Result<String> {
+        self.sess().0.backend.name(self.id)
+    }
+
+    // class is the WM_CLASS property, as (instance, class)
+    pub(crate) fn class(&self) -> Result<Option<(String, String)>> {
+        self.sess().0.backend.class(self.id)
+    }
+
+    pub(crate) fn role(&self) -> Result<Option<String>> {
+        self.sess().0.backend.role(self.id)
+    }
+
+    // is_viewable is true if the window is actually on screen
+    pub(crate) fn is_viewable(&self) -> Result<bool> {
+        self.sess().0.backend.is_viewable(self.id)
+    }
+
+    pub(crate) fn pid(&self) -> Result<Option<u32>> {
+        self.sess().0.backend.pid(self.id)
+    }
+
+    pub(crate) fn size_hints(&self) -> Result<SizeHints> {
+        self.sess().0.backend.size_hints(self.id)
+    }
+
+    // set_geom asks the WM to move and resize the window. our idea of the window geometry is then
+    // out of date; if we are watching, it will be updated when the WM has done it
+    pub(crate) fn set_geom(&self, geom: &Box2D) -> Result<()> {
+        self.sess().0.backend.set_geom(self.id, geom)
     }
 }
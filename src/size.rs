@@ -0,0 +1,291 @@
+use crate::condargs::{ConditionArg, OrderedComparator};
+
+use std::str::FromStr;
+
+// Size is a length along one axis, relative to the length available. Its written as a sum of
+// terms, each of which is pixels (800px), a percentage (33.3%, or just 33), or a fraction of the
+// available length (1/3), eg 100%-40px. "keep" is whatever the window has now.
+//
+// the fraction is kept exactly, as num/den, so that it can be rounded down the same way as
+// percentages always have been (and still are, for tile). in floating point, 29% of 100 comes out
+// just under 29
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Size {
+    Keep,
+    Expr { num: i64, den: i64, pixels: i32 },
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct SizeParseError;
+
+impl FromStr for Size {
+    type Err = SizeParseError;
+    fn from_str(s: &str) -> Result<Self, SizeParseError> {
+        if s == "keep" {
+            return Ok(Size::Keep);
+        }
+
+        // split into signed terms at each + or -, except a leading one
+        let mut terms = vec![];
+        let mut start = 0;
+        for (i, c) in s.char_indices() {
+            if i > 0 && (c == '+' || c == '-') {
+                terms.push(&s[start..i]);
+                start = i;
+            }
+        }
+        terms.push(&s[start..]);
+
+        let (mut num, mut den, mut pixels) = (0, 1, 0i32);
+        for term in terms {
+            let (sign, term) = match term.strip_prefix('-') {
+                Some(t) => (-1, t),
+                None => (1, term.strip_prefix('+').unwrap_or(term)),
+            };
+            if let Some(px) = term.strip_suffix("px") {
+                let px = px.parse::<i32>().map_err(|_| SizeParseError)?;
+                pixels = pixels.checked_add(sign * px).ok_or(SizeParseError)?;
+                continue;
+            }
+            let (n, d) = match term.split_once('/') {
+                Some((n, d)) => {
+                    let (n, nd) = decimal(n)?;
+                    let (d, dd) = decimal(d)?;
+                    if d == 0 {
+                        return Err(SizeParseError);
+                    }
+                    (mul(n, dd)?, mul(nd, d)?)
+                }
+                // a bare number is a percentage, as it always has been
+                None => {
+                    let (pc, d) = decimal(term.strip_suffix('%').unwrap_or(term))?;
+                    (pc, mul(d, 100)?)
+                }
+            };
+            (num, den) = add(num, den, sign as i64 * n, d)?;
+        }
+
+        Ok(Size::Expr { num, den, pixels })
+    }
+}
+
+// decimal parses a non-negative number like 33 or 33.3, as a fraction
+fn decimal(s: &str) -> Result<(i64, i64), SizeParseError> {
+    let (whole, frac) = s.split_once('.').unwrap_or((s, ""));
+    let digits = || whole.bytes().chain(frac.bytes());
+    if digits().next().is_none() || !digits().all(|b| b.is_ascii_digit()) {
+        return Err(SizeParseError);
+    }
+    let num = digits().try_fold(0, |num, b| {
+        mul(num, 10)?
+            .checked_add((b - b'0') as i64)
+            .ok_or(SizeParseError)
+    })?;
+    let den = 10i64.checked_pow(frac.len() as u32).ok_or(SizeParseError)?;
+    Ok((num, den))
+}
+
+fn mul(a: i64, b: i64) -> Result<i64, SizeParseError> {
+    a.checked_mul(b).ok_or(SizeParseError)
+}
+
+// add adds two fractions, keeping them in lowest terms so they don't grow without end
+fn add(an: i64, ad: i64, bn: i64, bd: i64) -> Result<(i64, i64), SizeParseError> {
+    let num = mul(an, bd)?
+        .checked_add(mul(bn, ad)?)
+        .ok_or(SizeParseError)?;
+    let den = mul(ad, bd)?;
+    let gcd = gcd(num.unsigned_abs(), den.unsigned_abs()).max(1) as i64;
+    Ok((num / gcd, den / gcd))
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    match b {
+        0 => a,
+        b => gcd(b, a % b),
+    }
+}
+
+impl Size {
+    // resolve works out the length, rounding any fraction of a pixel down
+    fn resolve(&self, avail_len: i16, cur: i16) -> i32 {
+        match *self {
+            Size::Keep => cur as i32,
+            Size::Expr { num, den, pixels } => {
+                let frac = (num as i128 * avail_len as i128).div_euclid(den as i128);
+                (frac.clamp(i32::MIN as i128, i32::MAX as i128) as i32).saturating_add(pixels)
+            }
+        }
+    }
+
+    // of resolves the size against the available length, and the window's current length. it
+    // always comes out at least one pixel, because X won't have a zero-size window
+    pub(crate) fn of(&self, avail_len: i16, cur_len: i16) -> i16 {
+        self.resolve(avail_len, cur_len).clamp(1, i16::MAX as i32) as i16
+    }
+
+    // offset resolves the size as a distance from the start of the available length, for --x and
+    // --y. cur is the window's current distance, for keep
+    pub(crate) fn offset(&self, avail_len: i16, cur: i16) -> i16 {
+        self.resolve(avail_len, cur)
+            .clamp(i16::MIN as i32, i16::MAX as i32) as i16
+    }
+}
+
+// SizeArg is a single size, or a list of them to step through on each invocation, as
+// cycle:A,B,C
+#[derive(Debug, Clone)]
+pub(crate) enum SizeArg {
+    Fixed(Size),
+    Cycle(Vec<Size>),
+}
+
+impl FromStr for SizeArg {
+    type Err = SizeParseError;
+    fn from_str(s: &str) -> Result<Self, SizeParseError> {
+        match s.strip_prefix("cycle:") {
+            Some(list) => list
+                .split(',')
+                .map(|size| size.parse::<Size>())
+                .collect::<Result<Vec<_>, _>>()
+                .map(SizeArg::Cycle),
+            None => Ok(SizeArg::Fixed(s.parse::<Size>()?)),
+        }
+    }
+}
+
+// SizeForRatioArg is a size that only applies when the available area's width/height ratio
+// matches a condition, as SIZE@COND, eg 50@<1.5
+pub(crate) type SizeForRatioArg = ConditionArg<SizeArg, OrderedComparator<f32>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn size(s: &str) -> Size {
+        s.parse().unwrap_or_else(|e| panic!("{}: {:?}", s, e))
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(size("keep"), Size::Keep);
+        assert_eq!(
+            size("800px"),
+            Size::Expr {
+                num: 0,
+                den: 1,
+                pixels: 800
+            }
+        );
+        assert_eq!(
+            size("100%-40px"),
+            Size::Expr {
+                num: 1,
+                den: 1,
+                pixels: -40
+            }
+        );
+        assert_eq!(
+            size("33.3%"),
+            Size::Expr {
+                num: 333,
+                den: 1000,
+                pixels: 0
+            }
+        );
+
+        // the same fraction, written different ways
+        for s in [
+            "50", "50%", "1/2", "2/4", "0.5/1", "25%+1/4", "75-25%", "50.0",
+        ] {
+            assert_eq!(size(s), size("1/2"), "{}", s);
+        }
+
+        for s in [
+            "",
+            "px",
+            "%",
+            "50%%",
+            "1/0",
+            "1/",
+            "/2",
+            "abc",
+            "10em",
+            "1.2.3",
+            ".",
+            "--5",
+            "5-",
+            "+",
+            "inf",
+            "nan",
+            "1e3",
+            "keep+10px",
+        ] {
+            assert_eq!(s.parse::<Size>(), Err(SizeParseError), "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn resolve() {
+        // percentages round down, like they always have
+        assert_eq!(size("33").of(1920, 0), 633);
+        assert_eq!(size("29").of(100, 0), 29);
+        assert_eq!(size("33.3").of(1000, 0), 333);
+        assert_eq!(size("1/3").of(1920, 0), 640);
+        assert_eq!(size("2/3").of(1000, 0), 666);
+        assert_eq!(size("100%").of(1080, 0), 1080);
+        assert_eq!(size("100%-40px").of(1080, 0), 1040);
+        assert_eq!(size("1/2+10px").of(1001, 0), 510);
+        assert_eq!(size("800px").of(1080, 0), 800);
+        assert_eq!(size("keep").of(1080, 123), 123);
+
+        // a length is always at least a pixel, but an offset can go anywhere
+        assert_eq!(size("0").of(1080, 0), 1);
+        assert_eq!(size("10px-100%").of(1080, 0), 1);
+        assert_eq!(size("-10%").offset(1000, 0), -100);
+        assert_eq!(size("keep").offset(1000, -5), -5);
+    }
+
+    #[test]
+    fn cycle() {
+        match "cycle:1/3,50%,100%-40px".parse::<SizeArg>() {
+            Ok(SizeArg::Cycle(sizes)) => {
+                assert_eq!(sizes, vec![size("1/3"), size("1/2"), size("100%-40px")])
+            }
+            other => panic!("{:?}", other),
+        }
+        assert!(matches!(
+            "keep".parse::<SizeArg>(),
+            Ok(SizeArg::Fixed(Size::Keep))
+        ));
+
+        for s in ["cycle:", "cycle:50,", "cycle:50,wide", "cycle 50,60"] {
+            assert!(s.parse::<SizeArg>().is_err(), "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn conditions() {
+        let arg: SizeForRatioArg = "50@<1.5".parse().unwrap();
+        assert!(matches!(arg.value, SizeArg::Fixed(s) if s == size("50")));
+        assert!(arg.matches(1.2));
+        assert!(!arg.matches(1.5));
+
+        let arg: SizeForRatioArg = "100%-40px@>=1.5".parse().unwrap();
+        assert!(matches!(arg.value, SizeArg::Fixed(s) if s == size("100%-40px")));
+        assert!(arg.matches(1.5));
+        assert!(!arg.matches(1.2));
+
+        let arg: SizeForRatioArg = "cycle:1/3,1/2@!=1".parse().unwrap();
+        assert!(matches!(arg.value, SizeArg::Cycle(ref sizes) if sizes.len() == 2));
+        assert!(arg.matches(1.7) && !arg.matches(1.0));
+
+        // no condition matches everything
+        let arg: SizeForRatioArg = "1/3".parse().unwrap();
+        assert!(arg.matches(0.5) && arg.matches(3.0));
+
+        for s in ["50@", "50@1.5", "50@<", "50@<wide", "wide@<1.5"] {
+            assert!(s.parse::<SizeForRatioArg>().is_err(), "{:?}", s);
+        }
+    }
+}
@@ -0,0 +1,294 @@
+use crate::engine::{MonitorPolicyArgs, Screen};
+use crate::geom::*;
+use crate::session::{HistoryEntry, Session, Window, WindowState};
+use crate::{capture_screen, restore_entry};
+
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+// Snapshot is every window's position at some moment, stored as JSON in the state dir.
+//
+// positions are fractions of the monitor's available area, not pixels, so that a snapshot still
+// makes sense after the resolution changes, or if a monitor has gone away and the window has to
+// go somewhere else
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct Snapshot {
+    windows: Vec<SavedWindow>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct SavedWindow {
+    // identity, for finding the window again
+    instance: Option<String>,
+    class: Option<String>,
+    title: String,
+    pid: Option<u32>,
+
+    // the monitor it was on, by name and by position in case the name has changed
+    monitor: String,
+    monitor_index: usize,
+
+    // outer geometry, relative to the monitor's available area
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+
+    #[serde(default)]
+    maximized_vert: bool,
+    #[serde(default)]
+    maximized_horz: bool,
+    #[serde(default)]
+    fullscreen: bool,
+    #[serde(default)]
+    shaded: bool,
+}
+
+// Identity is what we know about a live window, to match against saved ones
+struct Identity {
+    id: u32,
+    instance: Option<String>,
+    class: Option<String>,
+    title: String,
+    pid: Option<u32>,
+}
+
+impl Identity {
+    fn of(w: &Window) -> Result<Identity> {
+        let (instance, class) = w.class()?.unzip();
+        Ok(Identity {
+            id: w.id,
+            instance,
+            class,
+            title: w.name()?,
+            pid: w.pid()?,
+        })
+    }
+
+    // score is how well a live window matches a saved one, or None if it can't be the same
+    // window. the class has to match; after that the title is the best clue (it tells apart
+    // windows of the same program), and then the pid (which won't survive a restart)
+    fn score(&self, saved: &SavedWindow) -> Option<u32> {
+        if self.class != saved.class || self.instance != saved.instance {
+            return None;
+        }
+        let title = self.title == saved.title;
+        let pid = saved.pid.is_some() && self.pid == saved.pid;
+        // with no class to go on, the title is all we have
+        if saved.class.is_none() && !title {
+            return None;
+        }
+        Some(title as u32 * 2 + pid as u32)
+    }
+}
+
+// snapshot_path is $XDG_STATE_HOME/mosaic/snapshots/NAME.json, or under ~/.local/state
+fn snapshot_path(name: &str) -> Result<PathBuf> {
+    anyhow::ensure!(
+        !name.is_empty() && !name.starts_with('.') && !name.contains('/'),
+        "invalid snapshot name {:?}",
+        name
+    );
+    let dir = match std::env::var_os("XDG_STATE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME").context("HOME not set")?)
+            .join(".local")
+            .join("state"),
+    };
+    Ok(dir
+        .join("mosaic")
+        .join("snapshots")
+        .join(format!("{}.json", name)))
+}
+
+pub(crate) fn save(sess: &Session, name: &str) -> Result<()> {
+    let path = snapshot_path(name)?;
+
+    let screen = capture_screen(sess)?;
+
+    let mut snapshot = Snapshot::default();
+    for id in sess.selectable() {
+        match sess.window(id).and_then(|w| save_window(&screen, &w)) {
+            Ok(saved) => {
+                debug!("saving window {}: {:?}", id, saved);
+                snapshot.windows.push(saved);
+            }
+            Err(e) => warn!("couldn't save window {}: {:#}", id, e),
+        }
+    }
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("couldn't create {}", dir.display()))?;
+    }
+    std::fs::write(&path, serde_json::to_string_pretty(&snapshot)?)
+        .with_context(|| format!("couldn't write {}", path.display()))?;
+
+    info!(
+        "saved {} windows to {}",
+        snapshot.windows.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+fn save_window(screen: &Screen, w: &Window) -> Result<SavedWindow> {
+    let ident = Identity::of(w).context("failed to get window properties")?;
+    let state = w.state().context("failed to get window state")?;
+
+    let frame = w
+        .frame_extents()
+        .context("failed to get window frame extents")?;
+    let geom = w.abs_geom()?.outer_box(frame);
+
+    let monitor_index = screen
+        .select_monitor(&geom, MonitorPolicyArgs::Overlap)
+        .context("couldn't determine which monitor contains window")?;
+    let monitor = &screen.monitors[monitor_index];
+    let avail = screen.avail_geom(&monitor.geom.into());
+
+    // an empty area (a monitor thats all struts, say) would give NaN, which can't be saved, so
+    // it counts as a pixel
+    let frac = |v: i16, min: i16, len: i16| (v - min) as f32 / len.max(1) as f32;
+
+    Ok(SavedWindow {
+        instance: ident.instance,
+        class: ident.class,
+        title: ident.title,
+        pid: ident.pid,
+        monitor: monitor.name.clone(),
+        monitor_index,
+        x: frac(geom.min.x, avail.min.x, avail.width()),
+        y: frac(geom.min.y, avail.min.y, avail.height()),
+        width: frac(geom.width(), 0, avail.width()),
+        height: frac(geom.height(), 0, avail.height()),
+        maximized_vert: state.contains(WindowState::MAXIMIZED_VERT),
+        maximized_horz: state.contains(WindowState::MAXIMIZED_HORZ),
+        fullscreen: state.contains(WindowState::FULLSCREEN),
+        shaded: state.contains(WindowState::SHADED),
+    })
+}
+
+pub(crate) fn restore(sess: &Session, name: &str) -> Result<()> {
+    let path = snapshot_path(name)?;
+    let text = std::fs::read_to_string(&path)
+        .with_context(|| format!("couldn't read snapshot {}", path.display()))?;
+    let snapshot: Snapshot = serde_json::from_str(&text)
+        .with_context(|| format!("couldn't parse snapshot {}", path.display()))?;
+
+    let screen = capture_screen(sess)?;
+
+    let live: Vec<_> = sess
+        .selectable()
+        .filter_map(|id| match sess.window(id).and_then(|w| Identity::of(&w)) {
+            Ok(ident) => Some(ident),
+            Err(e) => {
+                warn!("couldn't get properties for window {}: {}", id, e);
+                None
+            }
+        })
+        .collect();
+
+    // each live window can only be used once, so two saved windows of the same program go to
+    // two different windows
+    let mut used = BTreeSet::new();
+    let mut restored = 0;
+
+    for saved in &snapshot.windows {
+        let best = live
+            .iter()
+            .filter(|ident| !used.contains(&ident.id))
+            .filter_map(|ident| Some((ident.score(saved)?, ident.id)))
+            // highest score wins, and the lowest id breaks ties, so the result is stable
+            .max_by_key(|&(score, id)| (score, std::cmp::Reverse(id)));
+
+        let Some((_, id)) = best else {
+            debug!("no window found for {:?}", saved);
+            continue;
+        };
+        used.insert(id);
+
+        debug!("restoring window {} to {:?}", id, saved);
+        match sess
+            .window(id)
+            .and_then(|w| restore_window(&screen, &w, saved))
+        {
+            Ok(()) => restored += 1,
+            Err(e) => warn!("couldn't restore window {}: {:#}", id, e),
+        }
+    }
+
+    info!(
+        "restored {} of {} windows from {}",
+        restored,
+        snapshot.windows.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+fn restore_window(screen: &Screen, target: &Window, saved: &SavedWindow) -> Result<()> {
+    let frame = target
+        .frame_extents()
+        .context("failed to get window frame extents")?;
+    let current_geom = target.abs_geom()?.outer_box(frame);
+    let current_state = target.state().context("failed to get window state")?;
+
+    // the same monitor by name if its still there, or by position if not. if there aren't
+    // that many monitors any more, leave the window on the one its on now
+    let monitor = screen
+        .monitors
+        .iter()
+        .position(|m| m.name == saved.monitor)
+        .or_else(|| (saved.monitor_index < screen.monitors.len()).then_some(saved.monitor_index))
+        .or_else(|| screen.select_monitor(&current_geom, MonitorPolicyArgs::Overlap))
+        .context("couldn't find a monitor for window")?;
+    let avail = screen.avail_geom(&screen.monitor_geom(monitor));
+
+    let scale = |f: f32, len: i16| (f * len as f32).round() as i16;
+    let geom = Box2D::from_origin_and_size(
+        (
+            avail.min.x + scale(saved.x, avail.width()),
+            avail.min.y + scale(saved.y, avail.height()),
+        )
+            .into(),
+        (
+            scale(saved.width, avail.width()),
+            scale(saved.height, avail.height()),
+        )
+            .into(),
+    );
+
+    let mut state = WindowState::empty();
+    state.set(WindowState::MAXIMIZED_VERT, saved.maximized_vert);
+    state.set(WindowState::MAXIMIZED_HORZ, saved.maximized_horz);
+    state.set(WindowState::FULLSCREEN, saved.fullscreen);
+    state.set(WindowState::SHADED, saved.shaded);
+
+    // snapshots don't keep desktops, so the window stays on the one its on
+    restore_entry(
+        target,
+        frame,
+        &HistoryEntry {
+            geom,
+            state,
+            desktop: None,
+        },
+    )?;
+
+    // restoring is just another move, so it can be undone
+    let mut history = target.history().context("failed to get window history")?;
+    history.push(HistoryEntry {
+        geom: current_geom,
+        state: current_state,
+        desktop: target.desktop,
+    });
+    target
+        .set_history(&history)
+        .context("failed to update window history")?;
+
+    Ok(())
+}
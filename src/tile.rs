@@ -0,0 +1,319 @@
+use crate::engine::{finish, gap_box, gaps_and_margin, percent_of, Options};
+use crate::geom::*;
+use crate::grid::grid_line;
+use crate::session::Session;
+use crate::{apply, capture_screen, monitor_windows, plan, RootArgs, TileArgs};
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use log::{debug, warn};
+
+// Layout is a way of arranging a set of windows to fill an area
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Layout {
+    // one big window on one side, the rest stacked on the other
+    MasterLeft,
+    MasterRight,
+    MasterTop,
+    MasterBottom,
+    Columns,
+    Rows,
+    Grid,
+    // each window takes half of what's left, turning clockwise
+    Spiral,
+    // every window gets the whole area
+    Monocle,
+}
+
+// tile arranges all the visible windows on the current monitor. the oldest window is the master
+pub(crate) fn tile(sess: &Session, args: &RootArgs, tile_args: &TileArgs) -> Result<()> {
+    let screen = capture_screen(sess)?;
+    let (monitor, ids) = monitor_windows(sess, &screen)?;
+
+    let mut windows = vec![];
+    for id in ids {
+        match sess.window(id).and_then(|w| w.is_viewable()) {
+            Ok(true) => windows.push(id),
+            Ok(false) => debug!("window {} isn't viewable, leaving it alone", id),
+            Err(e) => warn!("couldn't get attributes for window {}: {}", id, e),
+        }
+    }
+    anyhow::ensure!(!windows.is_empty(), "no windows on the current monitor");
+
+    let plans = windows
+        .iter()
+        .map(|&id| plan(sess, &screen, args, id))
+        .collect::<Result<Vec<_>>>()?;
+
+    let avail = screen.avail_geom(&screen.monitor_geom(monitor));
+    let (area, gaps) = gaps_and_margin(&avail, tile_args.gap, tile_args.margin);
+    debug!(
+        "tiling {} windows with {:?} in {:?}, gaps {:?}",
+        plans.len(),
+        tile_args.layout,
+        area,
+        gaps
+    );
+
+    let opts = Options::from(args);
+    let boxes = layout(tile_args.layout, &area, plans.len(), tile_args.master);
+    for (p, geom) in plans.iter().zip(boxes) {
+        let geom = gap_box(&geom, &area, gaps);
+        debug!("window {} gets {:?}", p.target.id, geom);
+        apply(p, &finish(&geom, &p.window, &avail, &opts))
+            .with_context(|| format!("failed to place window {}", p.target.id))?;
+    }
+
+    Ok(())
+}
+
+// layout computes the box for each of n windows in the area, in order. for the master layouts,
+// the first window is the master, and master is its share of the area, in percent
+pub(crate) fn layout(layout: Layout, area: &Box2D, n: usize, master: i32) -> Vec<Box2D> {
+    match (layout, n) {
+        (_, 0) => vec![],
+        (Layout::Monocle, _) | (_, 1) => vec![*area; n],
+
+        (Layout::Columns, _) => columns(area, n),
+        (Layout::Rows, _) => rows(area, n),
+
+        (Layout::MasterLeft, _) => {
+            let split = area.min.x + percent_of(master, area.width());
+            let (m, rest) = split_x(area, split);
+            std::iter::once(m).chain(rows(&rest, n - 1)).collect()
+        }
+        (Layout::MasterRight, _) => {
+            let split = area.max.x - percent_of(master, area.width());
+            let (rest, m) = split_x(area, split);
+            std::iter::once(m).chain(rows(&rest, n - 1)).collect()
+        }
+        (Layout::MasterTop, _) => {
+            let split = area.min.y + percent_of(master, area.height());
+            let (m, rest) = split_y(area, split);
+            std::iter::once(m).chain(columns(&rest, n - 1)).collect()
+        }
+        (Layout::MasterBottom, _) => {
+            let split = area.max.y - percent_of(master, area.height());
+            let (rest, m) = split_y(area, split);
+            std::iter::once(m).chain(columns(&rest, n - 1)).collect()
+        }
+
+        (Layout::Grid, _) => {
+            // as square as we can get. if the last row isn't full, its windows share it out
+            let cols = (n as f32).sqrt().ceil() as usize;
+            let nrows = n.div_ceil(cols);
+            rows(area, nrows)
+                .iter()
+                .enumerate()
+                .flat_map(|(r, row)| columns(row, cols.min(n - r * cols)))
+                .collect()
+        }
+
+        (Layout::Spiral, _) => {
+            let mut boxes = vec![];
+            let mut rest = *area;
+            for i in 0..n - 1 {
+                let (w, r) = match i % 4 {
+                    0 => split_x(&rest, rest.center().x),
+                    1 => split_y(&rest, rest.center().y),
+                    2 => {
+                        let (r, w) = split_x(&rest, rest.center().x);
+                        (w, r)
+                    }
+                    _ => {
+                        let (r, w) = split_y(&rest, rest.center().y);
+                        (w, r)
+                    }
+                };
+                boxes.push(w);
+                rest = r;
+            }
+            boxes.push(rest);
+            boxes
+        }
+    }
+}
+
+fn columns(area: &Box2D, n: usize) -> Vec<Box2D> {
+    let n = n as u16;
+    (0..n)
+        .map(|i| {
+            Box2D::new(
+                (grid_line(area.min.x, area.width(), n, i), area.min.y).into(),
+                (grid_line(area.min.x, area.width(), n, i + 1), area.max.y).into(),
+            )
+        })
+        .collect()
+}
+
+fn rows(area: &Box2D, n: usize) -> Vec<Box2D> {
+    let n = n as u16;
+    (0..n)
+        .map(|i| {
+            Box2D::new(
+                (area.min.x, grid_line(area.min.y, area.height(), n, i)).into(),
+                (area.max.x, grid_line(area.min.y, area.height(), n, i + 1)).into(),
+            )
+        })
+        .collect()
+}
+
+// split an area into the parts left and right of x
+fn split_x(area: &Box2D, x: i16) -> (Box2D, Box2D) {
+    (
+        Box2D::new(area.min, (x, area.max.y).into()),
+        Box2D::new((x, area.min.y).into(), area.max),
+    )
+}
+
+// split an area into the parts above and below y
+fn split_y(area: &Box2D, y: i16) -> (Box2D, Box2D) {
+    (
+        Box2D::new(area.min, (area.max.x, y).into()),
+        Box2D::new((area.min.x, y).into(), area.max),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: i16, y: i16, w: i16, h: i16) -> Box2D {
+        Box2D::from_origin_and_size((x, y).into(), (w, h).into())
+    }
+
+    const AREA: Box2D = Box2D::new(euclid::point2(0, 0), euclid::point2(1200, 900));
+
+    #[test]
+    fn simple_layouts() {
+        assert_eq!(layout(Layout::Columns, &AREA, 0, 50), vec![]);
+        for l in Layout::value_variants() {
+            assert_eq!(layout(*l, &AREA, 1, 50), vec![AREA], "{:?}", l);
+        }
+
+        assert_eq!(layout(Layout::Monocle, &AREA, 3, 50), vec![AREA; 3]);
+        assert_eq!(
+            layout(Layout::Columns, &AREA, 3, 50),
+            vec![
+                rect(0, 0, 400, 900),
+                rect(400, 0, 400, 900),
+                rect(800, 0, 400, 900)
+            ]
+        );
+        assert_eq!(
+            layout(Layout::Rows, &AREA, 3, 50),
+            vec![
+                rect(0, 0, 1200, 300),
+                rect(0, 300, 1200, 300),
+                rect(0, 600, 1200, 300)
+            ]
+        );
+
+        // the leftover pixels are spread out, and the last one reaches the edge
+        assert_eq!(
+            layout(Layout::Columns, &rect(10, 0, 1000, 900), 3, 50),
+            vec![
+                rect(10, 0, 333, 900),
+                rect(343, 0, 333, 900),
+                rect(676, 0, 334, 900)
+            ]
+        );
+    }
+
+    #[test]
+    fn master_layouts() {
+        assert_eq!(
+            layout(Layout::MasterLeft, &AREA, 3, 60),
+            vec![
+                rect(0, 0, 720, 900),
+                rect(720, 0, 480, 450),
+                rect(720, 450, 480, 450)
+            ]
+        );
+        assert_eq!(
+            layout(Layout::MasterRight, &AREA, 3, 60),
+            vec![
+                rect(480, 0, 720, 900),
+                rect(0, 0, 480, 450),
+                rect(0, 450, 480, 450)
+            ]
+        );
+        assert_eq!(
+            layout(Layout::MasterTop, &AREA, 3, 50),
+            vec![
+                rect(0, 0, 1200, 450),
+                rect(0, 450, 600, 450),
+                rect(600, 450, 600, 450)
+            ]
+        );
+        assert_eq!(
+            layout(Layout::MasterBottom, &AREA, 3, 50),
+            vec![
+                rect(0, 450, 1200, 450),
+                rect(0, 0, 600, 450),
+                rect(600, 0, 600, 450)
+            ]
+        );
+    }
+
+    #[test]
+    fn grid_layout() {
+        // five windows is three columns, and the two on the last row share it
+        assert_eq!(
+            layout(Layout::Grid, &AREA, 5, 50),
+            vec![
+                rect(0, 0, 400, 450),
+                rect(400, 0, 400, 450),
+                rect(800, 0, 400, 450),
+                rect(0, 450, 600, 450),
+                rect(600, 450, 600, 450)
+            ]
+        );
+    }
+
+    #[test]
+    fn spiral_layout() {
+        assert_eq!(
+            layout(Layout::Spiral, &AREA, 5, 50),
+            vec![
+                rect(0, 0, 600, 900),
+                rect(600, 0, 600, 450),
+                rect(900, 450, 300, 450),
+                rect(600, 675, 300, 225),
+                rect(600, 450, 300, 225)
+            ]
+        );
+    }
+
+    // apart from monocle, the boxes should cover the area exactly, without overlapping
+    #[test]
+    fn layouts_fill_area() {
+        let area = rect(7, 3, 1001, 757);
+        let size = |b: &Box2D| b.width() as i32 * b.height() as i32;
+        for &l in Layout::value_variants() {
+            if l == Layout::Monocle {
+                continue;
+            }
+            for n in 1..12 {
+                let boxes = layout(l, &area, n, 37);
+                assert_eq!(boxes.len(), n, "{:?} {}", l, n);
+
+                let total: i32 = boxes.iter().map(size).sum();
+                assert_eq!(total, size(&area), "{:?} {}", l, n);
+                for (i, a) in boxes.iter().enumerate() {
+                    assert!(area.contains_box(a), "{:?} {}: {:?}", l, n, a);
+                    for b in &boxes[i + 1..] {
+                        assert!(
+                            a.intersection(b).is_none_or(|o| o.is_empty()),
+                            "{:?} {}: {:?} overlaps {:?}",
+                            l,
+                            n,
+                            a,
+                            b
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,1111 @@
+use crate::backend::{Backend, Event, WindowInfo};
+use crate::geom::*;
+use crate::keys::{Keycode, Modifiers};
+use crate::session::{
+    HistoryEntry, KeyPress, Monitor, SizeHints, Strut, StrutEdge, WindowState, WindowType,
+};
+
+use anyhow::Result;
+use log::{debug, warn};
+use std::cell::{Cell, RefCell};
+use std::os::fd::{AsRawFd, BorrowedFd};
+use xcb::{randr, x, Xid, XidNew};
+
+xcb::atoms_struct! {
+    #[derive(Copy, Clone, Debug)]
+    struct Atoms {
+        wm_state => b"WM_STATE",
+
+        net_wm_window_type => b"_NET_WM_WINDOW_TYPE",
+        net_wm_window_type_normal => b"_NET_WM_WINDOW_TYPE_NORMAL",
+        net_wm_window_type_dock => b"_NET_WM_WINDOW_TYPE_DOCK",
+        net_wm_window_type_desktop => b"_NET_WM_WINDOW_TYPE_DESKTOP",
+
+        net_active_window => b"_NET_ACTIVE_WINDOW",
+
+        net_current_desktop => b"_NET_CURRENT_DESKTOP",
+        net_number_of_desktops => b"_NET_NUMBER_OF_DESKTOPS",
+        net_wm_desktop => b"_NET_WM_DESKTOP",
+        net_workarea => b"_NET_WORKAREA",
+        net_wm_strut => b"_NET_WM_STRUT",
+        net_wm_strut_partial => b"_NET_WM_STRUT_PARTIAL",
+
+        net_frame_extents => b"_NET_FRAME_EXTENTS",
+        gtk_frame_extents => b"_GTK_FRAME_EXTENTS",
+
+        net_moveresize_window => b"_NET_MOVERESIZE_WINDOW",
+
+        net_wm_name => b"_NET_WM_NAME",
+        net_wm_pid => b"_NET_WM_PID",
+        wm_window_role => b"WM_WINDOW_ROLE",
+        utf8_string => b"UTF8_STRING",
+
+        net_wm_state => b"_NET_WM_STATE",
+        net_wm_state_maximized_vert => b"_NET_WM_STATE_MAXIMIZED_VERT",
+        net_wm_state_maximized_horz => b"_NET_WM_STATE_MAXIMIZED_HORZ",
+        net_wm_state_fullscreen => b"_NET_WM_STATE_FULLSCREEN",
+        net_wm_state_shaded => b"_NET_WM_STATE_SHADED",
+
+        mosaic_history => b"_MOSAIC_HISTORY",
+    }
+}
+
+bitflags::bitflags! {
+    struct MoveResizeWindowFlags: u32 {
+        const GRAVITY_IMPLIED    = 0;
+        const GRAVITY_NORTH_WEST = 1;
+        const GRAVITY_NORTH      = 2;
+        const GRAVITY_NORTH_EAST = 3;
+        const GRAVITY_WEST       = 4;
+        const GRAVITY_CENTER     = 5;
+        const GRAVITY_EAST       = 6;
+        const GRAVITY_SOUTH_WEST = 7;
+        const GRAVITY_SOUTH      = 8;
+        const GRAVITY_SOUTH_EAST = 9;
+        const GRAVITY_STATIC     = 10;
+        const X                  = 1 << 8;
+        const Y                  = 1 << 9;
+        const WIDTH              = 1 << 10;
+        const HEIGHT             = 1 << 11;
+    }
+}
+
+// _NET_WM_STATE client message actions
+const NET_WM_STATE_REMOVE: u32 = 0;
+const NET_WM_STATE_ADD: u32 = 1;
+
+// the desktop in a history entry for a window that wasn't on one. not STICKY_DESKTOP, because
+// thats a real place to put a window back
+const NO_DESKTOP: u32 = 0xFFFFFFFE;
+
+// max property length in 4-byte ints. 2K is overkill but enough for any property we want
+const PROPERTY_LONG_LENGTH: u32 = 512;
+
+// modifiers that don't change what a key chord means: CapsLock and (usually) NumLock
+const IGNORED_MODS: Modifiers = Modifiers::LOCK.union(Modifiers::MOD2);
+
+// X cursor font glyph index for window selection
+const XC_CROSSHAIR: u16 = 34;
+const XC_CROSSHAIR_MASK: u16 = 35;
+
+// WM_SIZE_HINTS flags for the fields we care about
+const P_MIN_SIZE: u32 = 1 << 4;
+const P_MAX_SIZE: u32 = 1 << 5;
+const P_RESIZE_INC: u32 = 1 << 6;
+const P_ASPECT: u32 = 1 << 7;
+const P_BASE_SIZE: u32 = 1 << 8;
+
+// X11 is the backend for a real X server
+pub(crate) struct X11 {
+    conn: xcb::Connection,
+    atoms: Atoms,
+    root: x::Window,
+    watching: Cell<bool>,
+    // events that arrived while we were waiting for something else, for the next poll_events
+    pending: RefCell<Vec<Event>>,
+}
+
+// WindowCookies are the outstanding requests for everything we want to know about a window, so we
+// can send them for lots of windows at once, and then wait for them all
+struct WindowCookies {
+    xw: x::Window,
+    parent: u32,
+    geom: x::GetGeometryCookie,
+    state_prop: x::GetPropertyCookie,
+    type_prop: x::GetPropertyCookie,
+    strut_partial_prop: x::GetPropertyCookie,
+    strut_prop: x::GetPropertyCookie,
+    desktop_prop: x::GetPropertyCookie,
+}
+
+// xw turns a window id back into an X window
+fn xw(id: u32) -> x::Window {
+    x::Window::new(id)
+}
+
+impl X11 {
+    pub(crate) fn connect() -> xcb::Result<X11> {
+        let (conn, scr_num) =
+            xcb::Connection::connect_with_extensions(None, &[], &[xcb::Extension::RandR])?;
+
+        let atoms = Atoms::intern_all(&conn)?;
+
+        let root = conn
+            .get_setup()
+            .roots()
+            .nth(scr_num as usize)
+            .unwrap()
+            .to_owned()
+            .root();
+
+        Ok(X11 {
+            conn,
+            atoms,
+            root,
+            watching: Cell::new(false),
+            pending: RefCell::new(vec![]),
+        })
+    }
+
+    fn has_randr(&self) -> bool {
+        self.conn
+            .active_extensions()
+            .any(|ext| ext == xcb::Extension::RandR)
+    }
+
+    fn randr_monitors(&self) -> xcb::Result<Vec<Monitor>> {
+        // we have to tell the server what version we speak, or it won't give us anything newer
+        // than 1.1
+        let version_cookie = self.conn.send_request(&randr::QueryVersion {
+            major_version: 1,
+            minor_version: 5,
+        });
+        let version = self.conn.wait_for_reply(version_cookie)?;
+        debug!(
+            "RandR version {}.{}",
+            version.major_version(),
+            version.minor_version()
+        );
+
+        // RRGetMonitors arrived in 1.5. it knows about monitors made of multiple outputs (eg
+        // tiled displays) and user-defined monitors, so its the best choice if we have it
+        if (version.major_version(), version.minor_version()) >= (1, 5) {
+            let monitors_cookie = self.conn.send_request(&randr::GetMonitors {
+                window: self.root,
+                get_active: true,
+            });
+            let reply = self.conn.wait_for_reply(monitors_cookie)?;
+
+            let cookies: Vec<_> = reply
+                .monitors()
+                .map(|m| {
+                    let geom = Box2D::from_origin_and_size(
+                        (m.x(), m.y()).into(),
+                        (m.width() as i16, m.height() as i16).into(),
+                    );
+                    let name_cookie = self.conn.send_request(&x::GetAtomName { atom: m.name() });
+                    (geom, name_cookie)
+                })
+                .collect();
+
+            let mut monitors = vec![];
+            for (geom, name_cookie) in cookies {
+                let name = self.conn.wait_for_reply(name_cookie)?.name().to_string();
+                debug!("RandR monitor {} box: {:?}", name, geom);
+                monitors.push(Monitor { name, geom });
+            }
+
+            if !monitors.is_empty() {
+                return Ok(monitors);
+            }
+        }
+
+        // older server, so take the active CRTCs. mirrored outputs share a CRTC, so we don't
+        // need to dedup them, but two CRTCs can still be configured over the same area
+        let res = self.conn.wait_for_reply(
+            self.conn
+                .send_request(&randr::GetScreenResourcesCurrent { window: self.root }),
+        )?;
+
+        let cookies: Vec<_> = res
+            .crtcs()
+            .iter()
+            .map(|&crtc| {
+                self.conn.send_request(&randr::GetCrtcInfo {
+                    crtc,
+                    config_timestamp: res.config_timestamp(),
+                })
+            })
+            .collect();
+
+        let mut monitors: Vec<Monitor> = vec![];
+        for cookie in cookies {
+            let info = self.conn.wait_for_reply(cookie)?;
+            if info.outputs().is_empty() || info.width() == 0 || info.height() == 0 {
+                continue;
+            }
+            let geom = Box2D::from_origin_and_size(
+                (info.x(), info.y()).into(),
+                (info.width() as i16, info.height() as i16).into(),
+            );
+            if monitors.iter().any(|m| m.geom == geom) {
+                continue;
+            }
+
+            // a CRTC doesn't have a name, so use the name of its first output
+            let output =
+                self.conn
+                    .wait_for_reply(self.conn.send_request(&randr::GetOutputInfo {
+                        output: info.outputs()[0],
+                        config_timestamp: res.config_timestamp(),
+                    }))?;
+            let name = String::from_utf8_lossy(output.name()).to_string();
+
+            debug!("RandR CRTC {} box: {:?}", name, geom);
+            monitors.push(Monitor { name, geom });
+        }
+
+        Ok(monitors)
+    }
+
+    // request_window sends all the requests for the things we want to know about a window. if
+    // we're watching, it also asks for events from the window, so we find out when things change.
+    // the root is left alone, because watch() has already asked for more from it, and this would
+    // replace that
+    fn request_window(&self, xw: x::Window, parent: u32) -> WindowCookies {
+        if self.watching.get() && xw != self.root {
+            self.conn.send_request(&x::ChangeWindowAttributes {
+                window: xw,
+                value_list: &[x::Cw::EventMask(
+                    x::EventMask::SUBSTRUCTURE_NOTIFY | x::EventMask::PROPERTY_CHANGE,
+                )],
+            });
+        }
+
+        WindowCookies {
+            xw,
+            parent,
+            geom: self.x_get_geometry(xw),
+            state_prop: self.x_get_property(xw, self.atoms.wm_state, x::ATOM_ANY),
+            type_prop: self.x_get_property(xw, self.atoms.net_wm_window_type, x::ATOM_ANY),
+            strut_partial_prop: self.x_get_property(
+                xw,
+                self.atoms.net_wm_strut_partial,
+                x::ATOM_CARDINAL,
+            ),
+            strut_prop: self.x_get_property(xw, self.atoms.net_wm_strut, x::ATOM_CARDINAL),
+            desktop_prop: self.x_get_property(xw, self.atoms.net_wm_desktop, x::ATOM_CARDINAL),
+        }
+    }
+
+    // receive_window waits for the replies to the requests from request_window, and builds the
+    // WindowInfo from them
+    fn receive_window(&self, wc: WindowCookies, children: Vec<u32>) -> Option<WindowInfo> {
+        let geom = self.conn.wait_for_reply(wc.geom);
+        let state_prop = self.conn.wait_for_reply(wc.state_prop);
+        let type_prop = self.conn.wait_for_reply(wc.type_prop);
+
+        // struts are optional and uncommon, so if we can't get them we just assume there
+        // isn't one
+        let strut_partial_prop = self.conn.wait_for_reply(wc.strut_partial_prop).ok();
+        let strut_prop = self.conn.wait_for_reply(wc.strut_prop).ok();
+
+        // same for the desktop, which only clients (not frames or the root) will have
+        let desktop = self
+            .conn
+            .wait_for_reply(wc.desktop_prop)
+            .ok()
+            .and_then(|p| p.value::<u32>().first().copied());
+
+        let (geom, state_prop, type_prop) = match (geom, state_prop, type_prop) {
+            (Err(e), _, _) => {
+                warn!("GetGeometry for window {:?} failed: {}", wc.xw, e);
+                return None;
+            }
+            (_, Err(e), _) => {
+                warn!("GetProperty(WM_STATE) for window {:?} failed: {}", wc.xw, e);
+                return None;
+            }
+            (_, _, Err(e)) => {
+                warn!(
+                    "GetProperty(NET_WM_WINDOW_TYPE) for window {:?} failed: {}",
+                    wc.xw, e
+                );
+                return None;
+            }
+            (Ok(geom), Ok(state_prop), Ok(type_prop)) => (geom, state_prop, type_prop),
+        };
+
+        let id = wc.xw.resource_id();
+
+        let typ = match wc.xw == self.root {
+            true => WindowType::Root,
+            false => match type_prop.length() {
+                // some clients (Spotify) do not set a _NET_WM_WINDOW_TYPE at all.
+                // we already. we just treat them as TYPE_NORMAL here, because
+                // unless they've been selected somehow it won't even matter.
+                0 => WindowType::Normal,
+                _ => match type_prop.value::<x::Atom>()[0] {
+                    v if v == self.atoms.net_wm_window_type_dock => WindowType::Dock,
+                    v if v == self.atoms.net_wm_window_type_desktop => WindowType::Desktop,
+                    _ => WindowType::Normal,
+                },
+            },
+        };
+
+        // ICCCM mandates client root windows have WM_STATE, and we are only
+        // interested in NormalState (1) and in _NET_WM_WINDOW_TYPE_NORMAL
+        let selectable = state_prop.r#type() == self.atoms.wm_state
+            && state_prop.value::<u32>().first() == Some(&1)
+            && typ == WindowType::Normal;
+
+        let strut = strut_partial_prop
+            .and_then(|p| Strut::from_partial_prop(&p))
+            .or_else(|| strut_prop.and_then(|p| Strut::from_prop(&p)));
+        if strut.is_some() {
+            debug!("window {} has strut: {:?}", id, strut);
+        }
+
+        Some(WindowInfo {
+            id,
+            parent: wc.parent,
+            children,
+            geom: Box2D::from_origin_and_size(
+                (geom.x(), geom.y()).into(),
+                (geom.width() as i16, geom.height() as i16).into(),
+            ),
+            typ,
+            selectable,
+            strut,
+            desktop,
+        })
+    }
+
+    // translate_event turns an X event into the events the session cares about
+    fn translate_event(&self, ev: &xcb::Event) -> Vec<Event> {
+        debug!("event: {:?}", ev);
+
+        match ev {
+            xcb::Event::X(x::Event::KeyPress(ev)) => vec![Event::KeyPress(KeyPress {
+                keycode: ev.detail() as Keycode,
+                mods: Modifiers::from_bits_truncate(ev.state().bits()) - IGNORED_MODS,
+            })],
+
+            xcb::Event::RandR(randr::Event::ScreenChangeNotify(_)) => {
+                vec![Event::MonitorsChanged]
+            }
+
+            xcb::Event::X(x::Event::CreateNotify(ev)) => vec![Event::Created {
+                id: ev.window().resource_id(),
+                parent: ev.parent().resource_id(),
+            }],
+
+            xcb::Event::X(x::Event::DestroyNotify(ev)) => {
+                vec![Event::Destroyed(ev.window().resource_id())]
+            }
+
+            xcb::Event::X(x::Event::ConfigureNotify(ev)) => {
+                let mut events = vec![Event::Configured {
+                    id: ev.window().resource_id(),
+                    geom: Box2D::from_origin_and_size(
+                        (ev.x(), ev.y()).into(),
+                        (ev.width() as i16, ev.height() as i16).into(),
+                    ),
+                }];
+                // the root window changing size means the outputs changed
+                if ev.window() == self.root {
+                    events.push(Event::MonitorsChanged);
+                }
+                events
+            }
+
+            xcb::Event::X(x::Event::ReparentNotify(ev)) => vec![Event::Reparented {
+                id: ev.window().resource_id(),
+                parent: ev.parent().resource_id(),
+                pos: (ev.x(), ev.y()).into(),
+            }],
+
+            xcb::Event::X(x::Event::PropertyNotify(ev)) => {
+                let atoms = &self.atoms;
+                match [
+                    atoms.wm_state,
+                    atoms.net_wm_window_type,
+                    atoms.net_wm_strut,
+                    atoms.net_wm_strut_partial,
+                    atoms.net_wm_desktop,
+                ]
+                .contains(&ev.atom())
+                {
+                    true => vec![Event::Changed(ev.window().resource_id())],
+                    false => vec![],
+                }
+            }
+
+            _ => vec![],
+        }
+    }
+
+    // state_atoms is the atoms for each state flag
+    fn state_atoms(&self) -> [(WindowState, x::Atom); 4] {
+        let atoms = &self.atoms;
+        [
+            (
+                WindowState::MAXIMIZED_VERT,
+                atoms.net_wm_state_maximized_vert,
+            ),
+            (
+                WindowState::MAXIMIZED_HORZ,
+                atoms.net_wm_state_maximized_horz,
+            ),
+            (WindowState::FULLSCREEN, atoms.net_wm_state_fullscreen),
+            (WindowState::SHADED, atoms.net_wm_state_shaded),
+        ]
+    }
+
+    // send_to_wm sends a client message about a window to the root window, which is how we ask
+    // the WM to do things to it
+    fn send_to_wm(&self, id: u32, typ: x::Atom, data: [u32; 5]) {
+        let ev = x::ClientMessageEvent::new(xw(id), typ, x::ClientMessageData::Data32(data));
+        self.conn.send_request(&x::SendEvent {
+            propagate: false,
+            destination: x::SendEventDest::Window(self.root),
+            event_mask: x::EventMask::SUBSTRUCTURE_REDIRECT | x::EventMask::SUBSTRUCTURE_NOTIFY,
+            event: &ev,
+        });
+    }
+
+    fn root_cardinal(&self, prop: x::Atom) -> Option<u32> {
+        let prop = self
+            .conn
+            .wait_for_reply(self.x_get_property(self.root, prop, x::ATOM_CARDINAL))
+            .ok()?;
+        prop.value::<u32>().first().copied()
+    }
+
+    fn x_query_tree(&self, xw: x::Window) -> x::QueryTreeCookie {
+        self.conn.send_request(&x::QueryTree { window: xw })
+    }
+
+    fn x_get_geometry(&self, xw: x::Window) -> x::GetGeometryCookie {
+        self.conn.send_request(&x::GetGeometry {
+            drawable: x::Drawable::Window(xw),
+        })
+    }
+
+    fn x_get_property(&self, xw: x::Window, prop: x::Atom, ty: x::Atom) -> x::GetPropertyCookie {
+        self.conn.send_request(&x::GetProperty {
+            window: xw,
+            delete: false,
+            property: prop,
+            r#type: ty,
+            long_offset: 0,
+            long_length: PROPERTY_LONG_LENGTH,
+        })
+    }
+}
+
+impl Backend for X11 {
+    fn root(&self) -> u32 {
+        self.root.resource_id()
+    }
+
+    fn load_windows(&self) -> Vec<WindowInfo> {
+        fn get_window_state(
+            x11: &X11,
+            xw: x::Window,
+            parent: u32,
+        ) -> Vec<(WindowCookies, Vec<u32>)> {
+            let cookies = x11.request_window(xw, parent);
+            let tree_cookie = x11.x_query_tree(xw);
+
+            match x11.conn.wait_for_reply(tree_cookie) {
+                Ok(tree) => {
+                    let parent = xw.resource_id();
+                    let children = tree
+                        .children()
+                        .iter()
+                        .map(|&cxw| cxw.resource_id())
+                        .collect();
+
+                    std::iter::once((cookies, children))
+                        .chain(
+                            tree.children()
+                                .iter()
+                                .flat_map(|&cxw| get_window_state(x11, cxw, parent)),
+                        )
+                        .collect()
+                }
+                Err(e) => {
+                    warn!("QueryTree for window {:?} failed: {}", xw, e);
+                    vec![(cookies, vec![])]
+                }
+            }
+        }
+
+        get_window_state(self, self.root, self.root.resource_id())
+            .into_iter()
+            .filter_map(|(wc, children)| self.receive_window(wc, children))
+            .collect()
+    }
+
+    fn load_window(&self, id: u32, parent: u32) -> Option<WindowInfo> {
+        let wc = self.request_window(xw(id), parent);
+        self.receive_window(wc, vec![])
+    }
+
+    fn monitors(&self) -> Result<Vec<Monitor>> {
+        if !self.has_randr() {
+            debug!("RandR extension not available");
+            return Ok(vec![]);
+        }
+        let monitors = self.randr_monitors()?;
+        if monitors.is_empty() {
+            debug!("RandR reported no active monitors");
+        }
+        Ok(monitors)
+    }
+
+    fn current_desktop(&self) -> Option<u32> {
+        self.root_cardinal(self.atoms.net_current_desktop)
+    }
+
+    fn desktop_count(&self) -> Option<u32> {
+        self.root_cardinal(self.atoms.net_number_of_desktops)
+    }
+
+    fn workarea(&self) -> Option<Box2D> {
+        let prop = self
+            .conn
+            .wait_for_reply(self.x_get_property(
+                self.root,
+                self.atoms.net_workarea,
+                x::ATOM_CARDINAL,
+            ))
+            .ok()?;
+
+        // one x, y, width, height for each desktop
+        let desktop = self.current_desktop().unwrap_or(0) as usize;
+        match prop.value::<u32>().chunks_exact(4).nth(desktop) {
+            Some(&[x, y, w, h]) if w > 0 && h > 0 => Some(Box2D::from_origin_and_size(
+                (x as i16, y as i16).into(),
+                (w as i16, h as i16).into(),
+            )),
+            _ => None,
+        }
+    }
+
+    fn active_window(&self) -> Result<u32> {
+        let active_prop = self.conn.wait_for_reply(self.x_get_property(
+            self.root,
+            self.atoms.net_active_window,
+            x::ATOM_WINDOW,
+        ))?;
+        // nothing focused can be 0, or no value at all
+        Ok(active_prop.value::<u32>().first().copied().unwrap_or(0))
+    }
+
+    fn select_window(&self) -> Result<u32> {
+        let font = self.conn.generate_id();
+        self.conn.send_request(&x::OpenFont {
+            fid: font,
+            name: b"cursor",
+        });
+
+        let cursor = self.conn.generate_id();
+        self.conn.send_request(&x::CreateGlyphCursor {
+            cid: cursor,
+            source_font: font,
+            mask_font: font,
+            source_char: XC_CROSSHAIR,
+            mask_char: XC_CROSSHAIR_MASK,
+            fore_red: 0x0000,
+            fore_green: 0x0000,
+            fore_blue: 0x0000,
+            back_red: 0xffff,
+            back_green: 0xffff,
+            back_blue: 0xffff,
+        });
+
+        self.conn
+            .wait_for_reply(self.conn.send_request(&x::GrabPointer {
+                owner_events: false,
+                grab_window: self.root,
+                event_mask: x::EventMask::BUTTON_PRESS | x::EventMask::BUTTON_RELEASE,
+                pointer_mode: x::GrabMode::Sync,
+                keyboard_mode: x::GrabMode::Async,
+                confine_to: self.root,
+                cursor,
+                time: x::CURRENT_TIME,
+            }))?;
+
+        let selected = loop {
+            self.conn.send_request(&x::AllowEvents {
+                mode: x::Allow::SyncPointer,
+                time: x::CURRENT_TIME,
+            });
+            self.conn.flush()?;
+
+            match self.conn.wait_for_event() {
+                Ok(xcb::Event::X(x::Event::ButtonPress(ev))) => {
+                    let w = ev.child();
+                    if !w.is_none() {
+                        break w;
+                    }
+                }
+                // if we're watching, the world might change while we wait
+                Ok(ev) => {
+                    let events = self.translate_event(&ev);
+                    self.pending.borrow_mut().extend(events);
+                }
+                Err(xcb::Error::Protocol(e)) => debug!("ignoring X error: {:?}", e),
+                Err(e) => return Err(e.into()),
+            }
+        };
+
+        self.conn.send_request(&x::UngrabPointer {
+            time: x::CURRENT_TIME,
+        });
+        self.conn.flush()?;
+
+        Ok(selected.resource_id())
+    }
+
+    fn pointer(&self) -> Result<Point2D> {
+        let pointer = self.conn.wait_for_reply(
+            self.conn
+                .send_request(&x::QueryPointer { window: self.root }),
+        )?;
+        Ok((pointer.root_x(), pointer.root_y()).into())
+    }
+
+    fn frame_extents(&self, id: u32) -> Result<SideOffsets2D> {
+        // batch request for net_extents, because most windows won't have gtk_extents and we want
+        // to limit the wait as much as possible
+        let net_extents_prop_cookie =
+            self.x_get_property(xw(id), self.atoms.net_frame_extents, x::ATOM_CARDINAL);
+
+        // gtk apps with client-side decorations. _GTK_FRAME_EXTENTS is the border and shadow
+        // region of the client window. we prefer this over _NET_FRAME_EXTENTS, and because its
+        // "inside" the window, we negate the offsets
+        let gtk_extents_prop = self.conn.wait_for_reply(self.x_get_property(
+            xw(id),
+            self.atoms.gtk_frame_extents,
+            x::ATOM_CARDINAL,
+        ))?;
+
+        if gtk_extents_prop.r#type() == x::ATOM_CARDINAL
+            && let &[left, right, top, bottom, ..] = gtk_extents_prop.value::<u32>()
+        {
+            debug!(
+                "window {} using gtk frame extents: {:?}",
+                id,
+                gtk_extents_prop.value::<u32>()
+            );
+            // Cardinal order: left, right, top, bottom
+            // SideOffsets2D order: top, right, bottom, left
+            return Ok(-SideOffsets2D::new(
+                top as i16,
+                right as i16,
+                bottom as i16,
+                left as i16,
+            ));
+        }
+
+        // fall back to standard _NET_FRAME_EXTENTS
+        let net_extents_prop = self.conn.wait_for_reply(net_extents_prop_cookie)?;
+        match (net_extents_prop.r#type(), net_extents_prop.value::<u32>()) {
+            (x::ATOM_CARDINAL, &[left, right, top, bottom, ..]) => {
+                debug!(
+                    "window {} using net frame extents: {:?}",
+                    id,
+                    [left, right, top, bottom]
+                );
+                // Cardinal order: left, right, top, bottom
+                // SideOffsets2D order: top, right, bottom, left
+                Ok(SideOffsets2D::new(
+                    top as i16,
+                    right as i16,
+                    bottom as i16,
+                    left as i16,
+                ))
+            }
+            _ => {
+                debug!("window {} has no frame extents, assuming zero", id);
+                Ok(SideOffsets2D::zero())
+            }
+        }
+    }
+
+    fn state(&self, id: u32) -> Result<WindowState> {
+        let state_prop = self.conn.wait_for_reply(self.x_get_property(
+            xw(id),
+            self.atoms.net_wm_state,
+            x::ATOM_ATOM,
+        ))?;
+        if state_prop.r#type() != x::ATOM_ATOM {
+            return Ok(WindowState::empty());
+        }
+        let atoms: &[x::Atom] = state_prop.value();
+        Ok(self
+            .state_atoms()
+            .into_iter()
+            .filter(|(_, atom)| atoms.contains(atom))
+            .fold(WindowState::empty(), |state, (flag, _)| state | flag))
+    }
+
+    // history is kept in a property on the window, so it lives exactly as long as the window
+    // does, and any mosaic process can see it. each entry is six CARDINALs: x, y, width, height,
+    // state, desktop (NO_DESKTOP if there wasn't one)
+    fn history(&self, id: u32) -> Result<Vec<HistoryEntry>> {
+        let history_prop = self.conn.wait_for_reply(self.x_get_property(
+            xw(id),
+            self.atoms.mosaic_history,
+            x::ATOM_CARDINAL,
+        ))?;
+        if history_prop.r#type() != x::ATOM_CARDINAL {
+            return Ok(vec![]);
+        }
+        let v: &[u32] = history_prop.value();
+        Ok(v.chunks_exact(6)
+            .map(|e| HistoryEntry {
+                geom: Box2D::from_origin_and_size(
+                    (e[0] as i16, e[1] as i16).into(),
+                    (e[2] as i16, e[3] as i16).into(),
+                ),
+                state: WindowState::from_bits_truncate(e[4]),
+                desktop: (e[5] != NO_DESKTOP).then_some(e[5]),
+            })
+            .collect())
+    }
+
+    fn name(&self, id: u32) -> Result<String> {
+        // XXX some lazy cache for properties would be better
+        let net_name_prop_cookie =
+            self.x_get_property(xw(id), self.atoms.net_wm_name, self.atoms.utf8_string);
+        let name_prop =
+            self.conn
+                .wait_for_reply(self.x_get_property(xw(id), x::ATOM_WM_NAME, x::ATOM_ANY))?;
+        let net_name_prop = self.conn.wait_for_reply(net_name_prop_cookie)?;
+
+        // _NET_WM_NAME is UTF-8 and preferred. WM_NAME is usually Latin-1, but close enough
+        let prop = match net_name_prop.r#type() {
+            x::ATOM_NONE => name_prop,
+            _ => net_name_prop,
+        };
+        Ok(String::from_utf8_lossy(prop.value()).to_string())
+    }
+
+    fn class(&self, id: u32) -> Result<Option<(String, String)>> {
+        let class_prop = self.conn.wait_for_reply(self.x_get_property(
+            xw(id),
+            x::ATOM_WM_CLASS,
+            x::ATOM_STRING,
+        ))?;
+        let mut parts = class_prop
+            .value::<u8>()
+            .split(|&b| b == 0)
+            .map(|s| String::from_utf8_lossy(s).to_string());
+        match (parts.next(), parts.next()) {
+            (Some(instance), Some(class)) => Ok(Some((instance, class))),
+            _ => Ok(None),
+        }
+    }
+
+    fn role(&self, id: u32) -> Result<Option<String>> {
+        let role_prop = self.conn.wait_for_reply(self.x_get_property(
+            xw(id),
+            self.atoms.wm_window_role,
+            x::ATOM_STRING,
+        ))?;
+        match role_prop.r#type() {
+            x::ATOM_STRING => Ok(Some(
+                String::from_utf8_lossy(role_prop.value())
+                    .trim_end_matches('\0')
+                    .to_string(),
+            )),
+            _ => Ok(None),
+        }
+    }
+
+    fn pid(&self, id: u32) -> Result<Option<u32>> {
+        let pid_prop = self.conn.wait_for_reply(self.x_get_property(
+            xw(id),
+            self.atoms.net_wm_pid,
+            x::ATOM_CARDINAL,
+        ))?;
+        match pid_prop.r#type() {
+            x::ATOM_CARDINAL => Ok(pid_prop.value::<u32>().first().copied()),
+            _ => Ok(None),
+        }
+    }
+
+    // a window can be mapped, but still not visible because its frame isn't mapped, which is what
+    // most WMs do for windows on other workspaces
+    fn is_viewable(&self, id: u32) -> Result<bool> {
+        let attrs = self.conn.wait_for_reply(
+            self.conn
+                .send_request(&x::GetWindowAttributes { window: xw(id) }),
+        )?;
+        Ok(attrs.map_state() == x::MapState::Viewable)
+    }
+
+    fn size_hints(&self, id: u32) -> Result<SizeHints> {
+        let hints_prop = self.conn.wait_for_reply(self.x_get_property(
+            xw(id),
+            x::ATOM_WM_NORMAL_HINTS,
+            x::ATOM_WM_SIZE_HINTS,
+        ))?;
+        Ok(SizeHints::from_prop(&hints_prop))
+    }
+
+    fn set_geom(&self, id: u32, geom: &Box2D) -> Result<()> {
+        self.send_to_wm(
+            id,
+            self.atoms.net_moveresize_window,
+            [
+                (MoveResizeWindowFlags::X
+                    | MoveResizeWindowFlags::Y
+                    | MoveResizeWindowFlags::WIDTH
+                    | MoveResizeWindowFlags::HEIGHT
+                    | MoveResizeWindowFlags::GRAVITY_STATIC)
+                    .bits(),
+                geom.min.x as u32,
+                geom.min.y as u32,
+                geom.width() as u32,
+                geom.height() as u32,
+            ],
+        );
+        self.conn.flush()?;
+
+        // our idea of the window geometry is now out of date. if we are watching, it will be
+        // updated when the ConfigureNotify arrives
+
+        Ok(())
+    }
+
+    fn set_state(&self, id: u32, state: WindowState, on: bool) -> Result<()> {
+        let action = if on {
+            NET_WM_STATE_ADD
+        } else {
+            NET_WM_STATE_REMOVE
+        };
+
+        // the message can carry two states at once, which matters for maximize, so the WM does
+        // both directions in one go
+        let atoms: Vec<_> = self
+            .state_atoms()
+            .into_iter()
+            .filter_map(|(flag, atom)| state.contains(flag).then_some(atom))
+            .collect();
+        for pair in atoms.chunks(2) {
+            self.send_to_wm(
+                id,
+                self.atoms.net_wm_state,
+                [
+                    action,
+                    pair[0].resource_id(),
+                    pair.get(1).map_or(0, |a| a.resource_id()),
+                    // source indication: normal application
+                    1,
+                    0,
+                ],
+            );
+        }
+
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    fn set_desktop(&self, id: u32, desktop: u32) -> Result<()> {
+        // source indication: normal application
+        self.send_to_wm(id, self.atoms.net_wm_desktop, [desktop, 1, 0, 0, 0]);
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    fn set_history(&self, id: u32, history: &[HistoryEntry]) -> Result<()> {
+        if history.is_empty() {
+            self.conn.send_and_check_request(&x::DeleteProperty {
+                window: xw(id),
+                property: self.atoms.mosaic_history,
+            })?;
+            return Ok(());
+        }
+
+        let data: Vec<u32> = history
+            .iter()
+            .flat_map(|e| {
+                [
+                    e.geom.min.x as u32,
+                    e.geom.min.y as u32,
+                    e.geom.width() as u32,
+                    e.geom.height() as u32,
+                    e.state.bits(),
+                    e.desktop.unwrap_or(NO_DESKTOP),
+                ]
+            })
+            .collect();
+        self.conn.send_and_check_request(&x::ChangeProperty {
+            mode: x::PropMode::Replace,
+            window: xw(id),
+            property: self.atoms.mosaic_history,
+            r#type: x::ATOM_CARDINAL,
+            data: &data,
+        })?;
+        Ok(())
+    }
+
+    // watch asks the server to tell us about changes to the window tree and the outputs. windows
+    // are asked for events as they're loaded, so the session has to load them all again
+    fn watch(&self) -> Result<()> {
+        self.watching.set(true);
+
+        self.conn
+            .send_and_check_request(&x::ChangeWindowAttributes {
+                window: self.root,
+                value_list: &[x::Cw::EventMask(
+                    x::EventMask::STRUCTURE_NOTIFY
+                        | x::EventMask::SUBSTRUCTURE_NOTIFY
+                        | x::EventMask::PROPERTY_CHANGE,
+                )],
+            })?;
+        if self.has_randr() {
+            self.conn.send_and_check_request(&randr::SelectInput {
+                window: self.root,
+                enable: randr::NotifyMask::SCREEN_CHANGE,
+            })?;
+        }
+
+        Ok(())
+    }
+
+    fn poll_events(&self) -> Result<Vec<Event>> {
+        let mut events = self.pending.take();
+        loop {
+            match self.conn.poll_for_event() {
+                Ok(Some(ev)) => events.extend(self.translate_event(&ev)),
+                Ok(None) => return Ok(events),
+                // errors for our requests come through here too. they're usually because a
+                // window went away while we were asking about it, and we'll hear about that
+                // soon enough
+                Err(xcb::Error::Protocol(e)) => debug!("ignoring X error: {:?}", e),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    fn event_fd(&self) -> Option<BorrowedFd<'_>> {
+        // SAFETY: the connection owns the fd, and keeps it open for as long as we're borrowed
+        Some(unsafe { BorrowedFd::borrow_raw(self.conn.as_raw_fd()) })
+    }
+
+    // the ignored modifiers are grabbed in every combination, so the chord still works with
+    // CapsLock or NumLock on. a keysym can be on more than one key, or on none
+    fn grab_key(&self, mods: Modifiers, keysym: u32) -> Result<Vec<Keycode>> {
+        let setup = self.conn.get_setup();
+        let (min, max) = (setup.min_keycode(), setup.max_keycode());
+
+        let mapping = self
+            .conn
+            .wait_for_reply(self.conn.send_request(&x::GetKeyboardMapping {
+                first_keycode: min,
+                count: max - min + 1,
+            }))?;
+
+        let per = mapping.keysyms_per_keycode() as usize;
+        let keycodes: Vec<_> = mapping
+            .keysyms()
+            .chunks(per)
+            .zip(min..=max)
+            .filter_map(|(keysyms, kc)| keysyms.contains(&keysym).then_some(kc))
+            .collect();
+
+        for &kc in &keycodes {
+            for extra in [
+                Modifiers::empty(),
+                Modifiers::LOCK,
+                Modifiers::MOD2,
+                Modifiers::LOCK | Modifiers::MOD2,
+            ] {
+                self.conn.send_and_check_request(&x::GrabKey {
+                    owner_events: false,
+                    grab_window: self.root,
+                    modifiers: x::ModMask::from_bits_truncate((mods | extra).bits()),
+                    key: kc,
+                    pointer_mode: x::GrabMode::Async,
+                    keyboard_mode: x::GrabMode::Async,
+                })?;
+            }
+        }
+
+        Ok(keycodes.into_iter().map(Keycode::from).collect())
+    }
+}
+
+impl Strut {
+    // _NET_WM_STRUT_PARTIAL: left, right, top, bottom, left_start_y, left_end_y, right_start_y,
+    // right_end_y, top_start_x, top_end_x, bottom_start_x, bottom_end_x
+    fn from_partial_prop(prop: &x::GetPropertyReply) -> Option<Strut> {
+        if prop.r#type() != x::ATOM_CARDINAL {
+            return None;
+        }
+        let v: &[u32] = prop.value();
+        if v.len() < 12 {
+            return None;
+        }
+        let edge = |size: u32, start: u32, end: u32| StrutEdge {
+            size: size as i16,
+            start: start as i16,
+            end: end as i16,
+        };
+        Strut {
+            left: edge(v[0], v[4], v[5]),
+            right: edge(v[1], v[6], v[7]),
+            top: edge(v[2], v[8], v[9]),
+            bottom: edge(v[3], v[10], v[11]),
+        }
+        .nonzero()
+    }
+
+    // _NET_WM_STRUT: left, right, top, bottom, each covering the entire edge
+    fn from_prop(prop: &x::GetPropertyReply) -> Option<Strut> {
+        if prop.r#type() != x::ATOM_CARDINAL {
+            return None;
+        }
+        let v: &[u32] = prop.value();
+        if v.len() < 4 {
+            return None;
+        }
+        let edge = |size: u32| StrutEdge {
+            size: size as i16,
+            start: 0,
+            end: i16::MAX,
+        };
+        Strut {
+            left: edge(v[0]),
+            right: edge(v[1]),
+            top: edge(v[2]),
+            bottom: edge(v[3]),
+        }
+        .nonzero()
+    }
+
+    // panels sometimes set an all-zero strut when they're hidden, which is the same as no strut
+    fn nonzero(self) -> Option<Strut> {
+        [self.left, self.right, self.top, self.bottom]
+            .iter()
+            .any(|e| e.size > 0)
+            .then_some(self)
+    }
+}
+
+impl SizeHints {
+    // WM_SIZE_HINTS: flags, x, y, width, height (all obsolete), min_width, min_height, max_width,
+    // max_height, width_inc, height_inc, min_aspect num/den, max_aspect num/den, base_width,
+    // base_height, win_gravity. older clients may leave off the last three
+    fn from_prop(prop: &x::GetPropertyReply) -> SizeHints {
+        let v: &[u32] = match prop.format() {
+            32 => prop.value(),
+            _ => &[],
+        };
+        if v.len() < 15 {
+            return SizeHints::default();
+        }
+        let flags = v[0];
+        let pair = |flag: u32, a: u32, b: u32| {
+            (flags & flag != 0 && (a > 0 || b > 0)).then_some((a as i16, b as i16))
+        };
+        let ratio = |num: u32, den: u32| num as f32 / den as f32;
+        SizeHints {
+            min: pair(P_MIN_SIZE, v[5], v[6]),
+            max: pair(P_MAX_SIZE, v[7], v[8]),
+            inc: pair(P_RESIZE_INC, v[9], v[10]).filter(|&(w, h)| w > 0 && h > 0),
+            aspect: (flags & P_ASPECT != 0 && v[12] > 0 && v[14] > 0)
+                .then(|| (ratio(v[11], v[12]), ratio(v[13], v[14]))),
+            base: match v.len() {
+                17.. => pair(P_BASE_SIZE, v[15], v[16]),
+                _ => None,
+            },
+        }
+    }
+}